@@ -1,47 +1,115 @@
+use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
-use crate::tmux::TmuxClient;
+use crate::ansi::StyledScreen;
+use crate::backend::PaneBackend;
+use crate::config::{IndicatorRule, ScreenRegion};
+use crate::indicators::{IndicatorMatch, IndicatorSet};
+use crate::normalize::Normalizer;
 use anyhow::Result;
 
 #[derive(Debug, Clone)]
 pub struct ActivityResult {
-    pub is_active: bool,           // 是否有活动（基于working标识）
-    pub has_working: bool,        // 是否有working标识
-    pub has_esc_interrupt: bool,  // 是否有esc interrupt标识
-    pub content_changed: bool,     // 内容是否有变化
-    pub last_content: String,     // 当前内容
+    pub is_active: bool,           // 是否有活动（基于配置的活动标识）
+    pub indicators: IndicatorMatch,  // 匹配到的活动标识
+    pub content_changed: bool,     // 规范化后的内容是否有变化
+    pub fingerprint: u64,          // 规范化内容（含滚动历史）的指纹
+    pub last_content: String,     // 当前内容（可见屏幕纯文本）
+    pub screen: StyledScreen,     // 带样式的屏幕内容，可能包含滚动历史
+    pub output_rate: Option<f64>, // 最近一段时间的输出速率（字节/秒），没有连续输出信号时为 None
+}
+
+/// 输出速率统计窗口
+const OUTPUT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// 根据输出字节总数的采样计算输出速率
+pub struct OutputRate {
+    samples: VecDeque<(Instant, u64)>,
+    window: Duration,
+}
+
+impl OutputRate {
+    pub fn new(window: Duration) -> Self {
+        OutputRate { samples: VecDeque::new(), window }
+    }
+
+    /// 记录当前的输出字节总数
+    pub fn record(&mut self, total_bytes: u64) {
+        self.record_at(Instant::now(), total_bytes);
+    }
+
+    pub fn record_at(&mut self, at: Instant, total_bytes: u64) {
+        self.samples.push_back((at, total_bytes));
+        // 丢弃窗口之外的采样，至少保留两个用于计算
+        while self.samples.len() > 2 && at.duration_since(self.samples[1].0) >= self.window {
+            self.samples.pop_front();
+        }
+    }
+
+    /// 窗口内的平均输出速率（字节/秒），采样不足时返回 None
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        let (first_at, first_bytes) = *self.samples.front()?;
+        let (last_at, last_bytes) = *self.samples.back()?;
+        let elapsed = last_at.duration_since(first_at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        Some(last_bytes.saturating_sub(first_bytes) as f64 / elapsed)
+    }
 }
 
 pub struct ActivityDetector {
-    tmux_client: TmuxClient,
-    last_content: String,
+    indicators: IndicatorSet,
+    normalizer: Normalizer,
+    last_screen: StyledScreen,
+    last_fingerprint: Option<u64>,
     last_content_change: Instant,
     last_check: Instant,
+    output_rate: OutputRate,
 }
 
 impl ActivityDetector {
-    pub fn new() -> Self {
+    pub fn new(indicators: &[IndicatorRule], regions: &HashMap<String, ScreenRegion>) -> Self {
         ActivityDetector {
-            tmux_client: TmuxClient::new(),
-            last_content: String::new(),
+            indicators: IndicatorSet::new(indicators, regions),
+            normalizer: Normalizer::default(),
+            last_screen: StyledScreen::default(),
+            last_fingerprint: None,
             last_content_change: Instant::now(),
             last_check: Instant::now(),
+            output_rate: OutputRate::new(OUTPUT_RATE_WINDOW),
         }
     }
+
+    /// 使用配置的规范化处理比较内容变化
+    pub fn with_normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
     
-    /// 第一阶段检测：基于working标识的快速检测
+    /// 第一阶段检测：基于活动标识的快速检测
     /// 返回详细的检测结果，包含多个维度的信息
-    pub fn check_activity(&mut self, pane: &str) -> Result<ActivityResult> {
-        let current_content = self.tmux_client.capture_pane_content(pane)?;
-        
-        // 检查是否有working或esc interrupt标识
-        let has_working = current_content.contains("working");
-        let has_esc_interrupt = current_content.contains("esc interrupt");
-        
+    pub async fn check_activity<B: PaneBackend + ?Sized>(&mut self, backend: &B, pane: &str) -> Result<ActivityResult> {
+        let screen = backend.capture_screen(pane).await?;
+        Ok(self.analyze(screen))
+    }
+
+    /// 记录输出来源（pipe-pane、控制模式、内置 PTY）报告的输出字节总数
+    pub fn record_output(&mut self, total_bytes: u64) {
+        self.output_rate.record(total_bytes);
+    }
+
+    /// 对一次捕获到的内容进行分析并更新内部状态
+    pub fn analyze(&mut self, screen: StyledScreen) -> ActivityResult {
+        let current_content = screen.visible_text();
+
         // 基于标识的活动状态（快速检测）
-        let is_active = has_working || has_esc_interrupt;
+        let indicators = self.indicators.check(&current_content);
+        let is_active = indicators.is_active();
         
         // 检查内容是否有变化（慢速检测用）
-        let content_changed = current_content != self.last_content;
+        // 包含滚动历史，这样两次检查之间滚出屏幕的输出也算变化；spinner、计时器和换行位置的变化不算
+        let fingerprint = self.normalizer.fingerprint(&screen.full_text());
+        let content_changed = self.last_fingerprint != Some(fingerprint);
         
         // 如果内容有变化，更新最后变化时间
         if content_changed {
@@ -49,20 +117,28 @@ impl ActivityDetector {
         }
         
         // 保存当前内容
-        self.last_content = current_content.clone();
+        self.last_screen = screen.clone();
+        self.last_fingerprint = Some(fingerprint);
         self.last_check = Instant::now();
         
-        Ok(ActivityResult {
+        ActivityResult {
             is_active,
-            has_working,
-            has_esc_interrupt,
+            indicators,
             content_changed,
+            fingerprint,
             last_content: current_content,
-        })
+            screen,
+            output_rate: self.output_rate.bytes_per_sec(),
+        }
     }
     
+    /// 在不重新捕获的情况下，对上次内容重新分析
+    pub fn reanalyze(&mut self) -> ActivityResult {
+        self.analyze(self.last_screen.clone())
+    }
+
     /// 第二阶段检测：检查长时间无内容变化
-    /// 用于检测"假工作状态"（有working标识但实际卡住）
+    /// 用于检测"假工作状态"（有活动标识但实际卡住）
     pub fn check_long_stuck(&self, duration_threshold: Duration) -> bool {
         let no_change_duration = self.last_content_change.elapsed();
         no_change_duration > duration_threshold
@@ -77,4 +153,26 @@ impl ActivityDetector {
     pub fn get_time_since_last_check(&self) -> Duration {
         self.last_check.elapsed()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_rate() {
+        let start = Instant::now();
+        let mut rate = OutputRate::new(Duration::from_secs(10));
+        assert_eq!(rate.bytes_per_sec(), None);
+
+        rate.record_at(start, 0);
+        assert_eq!(rate.bytes_per_sec(), None);
+        rate.record_at(start + Duration::from_secs(2), 1000);
+        assert_eq!(rate.bytes_per_sec(), Some(500.0));
+
+        // 超出窗口的旧采样被丢弃，速率只反映最近的输出
+        rate.record_at(start + Duration::from_secs(20), 1000);
+        rate.record_at(start + Duration::from_secs(22), 1000);
+        assert_eq!(rate.bytes_per_sec(), Some(0.0));
+    }
+}
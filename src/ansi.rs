@@ -0,0 +1,242 @@
+/// 终端颜色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),       // 0-255 调色板颜色（0-7 标准色，8-15 高亮色）
+    Rgb(u8, u8, u8),   // 真彩色
+}
+
+/// 单元格样式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+/// 带样式的字符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StyledCell {
+    pub ch: char,
+    pub style: Style,
+}
+
+/// 一行带样式的内容
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StyledLine {
+    pub cells: Vec<StyledCell>,
+}
+
+impl StyledLine {
+    /// 去掉样式后的纯文本
+    pub fn text(&self) -> String {
+        self.cells.iter().map(|cell| cell.ch).collect()
+    }
+}
+
+/// 一次捕获得到的屏幕内容
+/// lines 中 visible_start 之前的行来自滚动历史，之后的行是当前可见屏幕
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StyledScreen {
+    pub lines: Vec<StyledLine>,
+    pub visible_start: usize,
+}
+
+impl StyledScreen {
+    /// 从不含转义序列的纯文本构造，全部视为可见内容
+    pub fn from_plain(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| StyledLine {
+                cells: line.chars().map(|ch| StyledCell { ch, style: Style::default() }).collect(),
+            })
+            .collect();
+        StyledScreen { lines, visible_start: 0 }
+    }
+
+    /// 当前可见屏幕的行
+    pub fn visible_lines(&self) -> &[StyledLine] {
+        &self.lines[self.visible_start.min(self.lines.len())..]
+    }
+
+    /// 当前可见屏幕的纯文本
+    pub fn visible_text(&self) -> String {
+        join_lines(self.visible_lines())
+    }
+
+    /// 包含滚动历史的完整纯文本
+    pub fn full_text(&self) -> String {
+        join_lines(&self.lines)
+    }
+}
+
+fn join_lines(lines: &[StyledLine]) -> String {
+    lines.iter().map(|line| line.text() + "\n").collect()
+}
+
+/// 解析带 ANSI 转义序列的文本（capture-pane -e 的输出）
+/// 只解释 SGR 样式序列，其它 CSI / OSC 序列直接丢弃；样式跨行保持，与终端行为一致
+pub fn parse_ansi(text: &str) -> StyledScreen {
+    let mut lines = Vec::new();
+    let mut current = StyledLine::default();
+    let mut style = Style::default();
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    let mut params = String::new();
+                    let mut final_byte = None;
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            final_byte = Some(c);
+                            break;
+                        }
+                        params.push(c);
+                    }
+                    if final_byte == Some('m') {
+                        apply_sgr(&mut style, &params);
+                    }
+                }
+                Some(']') => {
+                    // OSC 序列以 BEL 或 ESC \ 结束
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\n' => lines.push(std::mem::take(&mut current)),
+            '\r' => {}
+            _ => current.cells.push(StyledCell { ch, style }),
+        }
+    }
+
+    if !current.cells.is_empty() {
+        lines.push(current);
+    }
+
+    StyledScreen { lines, visible_start: 0 }
+}
+
+/// 应用一组 SGR 参数
+pub fn apply_sgr(style: &mut Style, params: &str) {
+    let codes: Vec<u32> = if params.is_empty() {
+        vec![0]
+    } else {
+        params.split([';', ':']).map(|p| p.parse().unwrap_or(0)).collect()
+    };
+
+    let mut i = 0;
+    while i < codes.len() {
+        match codes[i] {
+            0 => *style = Style::default(),
+            1 => style.bold = true,
+            2 => style.dim = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            7 => style.reverse = true,
+            22 => {
+                style.bold = false;
+                style.dim = false;
+            }
+            23 => style.italic = false,
+            24 => style.underline = false,
+            27 => style.reverse = false,
+            code @ 30..=37 => style.fg = Color::Indexed((code - 30) as u8),
+            code @ 40..=47 => style.bg = Color::Indexed((code - 40) as u8),
+            code @ 90..=97 => style.fg = Color::Indexed((code - 90 + 8) as u8),
+            code @ 100..=107 => style.bg = Color::Indexed((code - 100 + 8) as u8),
+            39 => style.fg = Color::Default,
+            49 => style.bg = Color::Default,
+            code @ (38 | 48) => {
+                let (color, consumed) = parse_extended_color(&codes[i + 1..]);
+                if let Some(color) = color {
+                    if code == 38 {
+                        style.fg = color;
+                    } else {
+                        style.bg = color;
+                    }
+                }
+                i += consumed;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// 解析 38/48 之后的扩展颜色参数，返回颜色和消耗的参数个数
+fn parse_extended_color(rest: &[u32]) -> (Option<Color>, usize) {
+    match rest {
+        [5, index, ..] => (Some(Color::Indexed(*index as u8)), 2),
+        [2, r, g, b, ..] => (Some(Color::Rgb(*r as u8, *g as u8, *b as u8)), 4),
+        [5] | [2, ..] => (None, rest.len()),
+        _ => (None, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text() {
+        let screen = parse_ansi("hello\nworld\n");
+        assert_eq!(screen.lines.len(), 2);
+        assert_eq!(screen.full_text(), "hello\nworld\n");
+        assert!(screen.lines[0].cells.iter().all(|c| c.style == Style::default()));
+    }
+
+    #[test]
+    fn test_basic_colors() {
+        let screen = parse_ansi("\x1b[1;32mok\x1b[0m done");
+        let cells = &screen.lines[0].cells;
+        assert_eq!(screen.lines[0].text(), "ok done");
+        assert_eq!(cells[0].style.fg, Color::Indexed(2));
+        assert!(cells[0].style.bold);
+        assert_eq!(cells[2].style, Style::default());
+    }
+
+    #[test]
+    fn test_extended_colors() {
+        let screen = parse_ansi("\x1b[38;5;208ma\x1b[48;2;10;20;30mb\x1b[39;49mc");
+        let cells = &screen.lines[0].cells;
+        assert_eq!(cells[0].style.fg, Color::Indexed(208));
+        assert_eq!(cells[1].style.bg, Color::Rgb(10, 20, 30));
+        assert_eq!(cells[2].style, Style::default());
+    }
+
+    #[test]
+    fn test_bright_colors_and_style_persists_across_lines() {
+        let screen = parse_ansi("\x1b[91mred\nstill red\x1b[m\nplain");
+        assert_eq!(screen.lines[1].cells[0].style.fg, Color::Indexed(9));
+        assert_eq!(screen.lines[2].cells[0].style.fg, Color::Default);
+    }
+
+    #[test]
+    fn test_skips_non_sgr_sequences() {
+        let screen = parse_ansi("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x07\x1b[2Kx");
+        assert_eq!(screen.lines[0].text(), "linkx");
+    }
+
+    #[test]
+    fn test_visible_region() {
+        let mut screen = StyledScreen::from_plain("old 1\nold 2\nnow\n");
+        screen.visible_start = 2;
+        assert_eq!(screen.visible_text(), "now\n");
+        assert_eq!(screen.full_text(), "old 1\nold 2\nnow\n");
+    }
+}
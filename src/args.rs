@@ -1,22 +1,38 @@
-use clap::Parser;
+use std::path::PathBuf;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(name = "opencode-watch")]
 #[command(about = "一个极简守护进程，基于opencode活动检测监控opencode")]
 #[command(version = "1.0.0")]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// 配置文件路径
     #[arg(short, long, default_value = "config.yaml")]
     pub config: String,
     
-    /// 要监控的 tmux pane ID
+    /// 要监控的 tmux pane ID（auto 表示自动发现）
     #[arg(short, long)]
     pub pane: Option<String>,
     
+    /// 自动发现运行 agent 的 pane（等同于 --pane auto）
+    #[arg(long)]
+    pub discover: bool,
+    
     /// LLM 后端：ollama、openai、openrouter、none
     #[arg(short, long, value_parser = ["ollama", "openai", "openrouter", "none"])]
     pub backend: Option<String>,
     
+    /// 终端复用器：tmux、screen
+    #[arg(long, value_parser = ["tmux", "screen"])]
+    pub multiplexer: Option<String>,
+    
+    /// agent 配置档：opencode、claude、aider、codex、gemini-cli
+    #[arg(long, value_parser = ["opencode", "claude", "aider", "codex", "gemini-cli"])]
+    pub profile: Option<String>,
+    
     /// 检查间隔（秒）
     #[arg(short, long)]
     pub interval: Option<u64>,
@@ -28,4 +44,51 @@ pub struct Args {
     /// 最大重试次数
     #[arg(short = 'm', long)]
     pub max_retry: Option<usize>,
-}
\ No newline at end of file
+    
+    /// 使用 tmux 控制模式（tmux -C）实时接收 pane 输出
+    #[arg(long)]
+    pub control_mode: bool,
+
+    /// 在被监控的 pane 上安装 tmux hook，静默和退出事件由 tmux 主动推送
+    #[arg(long)]
+    pub tmux_hooks: bool,
+
+    /// 用 tmux pipe-pane 持续记录 pane 原始输出，并由内置虚拟终端还原屏幕
+    #[arg(long)]
+    pub pipe_pane: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 列出所有运行 agent 的 tmux pane 及其当前状态
+    ListPanes {
+        /// 列出全部 pane，而不仅是 agent pane
+        #[arg(short, long)]
+        all: bool,
+    },
+
+    /// 在内置伪终端中启动 agent 并直接监控它（不需要 tmux），例如 `run -- opencode`
+    Run {
+        /// 监控日志文件路径（运行期间终端被 agent 占用，日志写入文件）
+        #[arg(long, default_value = "opencode-watch.log")]
+        log: String,
+
+        /// 要运行的命令及其参数
+        #[arg(trailing_var_arg = true, required = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+
+    /// 由 tmux hook 调用，把事件通知给正在运行的监控进程
+    #[command(hide = true)]
+    Notify {
+        /// 监控进程的 socket 路径
+        #[arg(long)]
+        socket: PathBuf,
+
+        /// 事件名：silence、activity、bell、died
+        event: String,
+
+        /// 触发事件的 pane 或窗口标识
+        target: String,
+    },
+}
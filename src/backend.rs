@@ -0,0 +1,269 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use crate::ansi::StyledScreen;
+use crate::identity::PaneFingerprint;
+
+/// pane 元数据
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PaneMetadata {
+    pub pane_id: String,          // pane 唯一标识（例如 %18）
+    pub current_command: String,  // 前台进程命令名
+    pub title: String,            // pane 标题
+    pub current_path: String,     // 当前工作目录
+    pub pid: Option<u32>,         // pane 主进程 pid
+    pub width: u16,               // 宽度（列）
+    pub height: u16,              // 高度（行）
+    pub dead: bool,               // pane 中的程序已退出（remain-on-exit 保留了 pane）
+    pub activity: Option<u64>,    // 最近一次输出的时间戳（秒），未变化说明没有新输出；后端不提供时为 None
+}
+
+/// 终端后端抽象
+/// 监控与干预逻辑只依赖这个 trait，tmux 只是其中一种实现
+/// 方法都是异步的，后端的外部命令不会阻塞监控循环所在的运行时
+#[async_trait]
+pub trait PaneBackend: Send + Sync {
+    /// 捕获 pane 当前屏幕内容
+    async fn capture_pane_content(&self, pane: &str) -> Result<String>;
+
+    /// 捕获带样式的屏幕内容（可能包含滚动历史）
+    /// 默认实现把纯文本内容视为无样式的可见屏幕
+    async fn capture_screen(&self, pane: &str) -> Result<StyledScreen> {
+        Ok(StyledScreen::from_plain(&self.capture_pane_content(pane).await?))
+    }
+
+    /// 向 pane 发送按键（tmux 按键名，例如 Enter、C-c）
+    async fn send_keys(&self, pane: &str, keys: &str) -> Result<()>;
+
+    /// 向 pane 原样输入文本，不解释其中的按键名
+    /// 默认实现交给 send_keys，适用于本身就按原样输入的后端
+    async fn send_text(&self, pane: &str, text: &str) -> Result<()> {
+        self.send_keys(pane, text).await
+    }
+
+    /// 向 pane 粘贴多行文本，目标程序支持时使用 bracketed paste，换行不会被当作提交
+    async fn paste_text(&self, pane: &str, text: &str) -> Result<()> {
+        self.send_text(pane, text).await
+    }
+
+    /// 检查 pane 是否存在
+    /// Ok(false) 表示 pane 确实已不存在；无法确定时（例如 tmux 服务器无响应）返回错误
+    async fn check_pane_exists(&self, pane: &str) -> Result<bool>;
+
+    /// 获取 pane 元数据
+    async fn pane_metadata(&self, pane: &str) -> Result<PaneMetadata>;
+
+    /// 把配置中的目标解析为稳定的 pane 标识，默认原样使用
+    async fn resolve_pane(&self, target: &str) -> Result<String> {
+        Ok(target.to_string())
+    }
+
+    /// 获取 pane 主进程指纹，用于发现 pane 标识被回收后指向了其它程序
+    async fn pane_fingerprint(&self, pane: &str) -> Result<Option<PaneFingerprint>> {
+        Ok(self.pane_metadata(pane).await?.pid.map(PaneFingerprint::of_pid))
+    }
+
+    /// 结束 pane 中的程序（如果仍在运行）并在原 pane 中执行 command
+    async fn respawn_pane(&self, _pane: &str, _command: &str) -> Result<()> {
+        Err(anyhow::anyhow!("该后端不支持 respawn-pane"))
+    }
+}
+
+/// bracketed paste 起止标记
+pub const BRACKETED_PASTE_START: &str = "\x1b[200~";
+pub const BRACKETED_PASTE_END: &str = "\x1b[201~";
+
+/// 把 tmux 风格的按键名转换为终端输入字符，不是已知按键名时返回 None
+/// 供不能直接理解 tmux 按键名的后端（screen、内置 PTY）使用
+pub fn control_sequence(key: &str) -> Option<&'static str> {
+    match key {
+        "Enter" => Some("\r"),
+        "C-c" => Some("\x03"),
+        "C-d" => Some("\x04"),
+        "Escape" => Some("\x1b"),
+        "Tab" => Some("\t"),
+        "BSpace" => Some("\x7f"),
+        _ => None,
+    }
+}
+
+/// 允许多个监控任务共享同一个后端
+#[async_trait]
+impl<T: PaneBackend + ?Sized> PaneBackend for std::sync::Arc<T> {
+    async fn capture_pane_content(&self, pane: &str) -> Result<String> {
+        (**self).capture_pane_content(pane).await
+    }
+
+    async fn capture_screen(&self, pane: &str) -> Result<StyledScreen> {
+        (**self).capture_screen(pane).await
+    }
+
+    async fn send_keys(&self, pane: &str, keys: &str) -> Result<()> {
+        (**self).send_keys(pane, keys).await
+    }
+
+    async fn send_text(&self, pane: &str, text: &str) -> Result<()> {
+        (**self).send_text(pane, text).await
+    }
+
+    async fn paste_text(&self, pane: &str, text: &str) -> Result<()> {
+        (**self).paste_text(pane, text).await
+    }
+
+    async fn check_pane_exists(&self, pane: &str) -> Result<bool> {
+        (**self).check_pane_exists(pane).await
+    }
+
+    async fn pane_metadata(&self, pane: &str) -> Result<PaneMetadata> {
+        (**self).pane_metadata(pane).await
+    }
+
+    async fn resolve_pane(&self, target: &str) -> Result<String> {
+        (**self).resolve_pane(target).await
+    }
+
+    async fn pane_fingerprint(&self, pane: &str) -> Result<Option<PaneFingerprint>> {
+        (**self).pane_fingerprint(pane).await
+    }
+
+    async fn respawn_pane(&self, pane: &str, command: &str) -> Result<()> {
+        (**self).respawn_pane(pane, command).await
+    }
+}
+
+/// 允许按配置在运行时选择不同的后端
+#[async_trait]
+impl<T: PaneBackend + ?Sized> PaneBackend for Box<T> {
+    async fn capture_pane_content(&self, pane: &str) -> Result<String> {
+        (**self).capture_pane_content(pane).await
+    }
+
+    async fn capture_screen(&self, pane: &str) -> Result<StyledScreen> {
+        (**self).capture_screen(pane).await
+    }
+
+    async fn send_keys(&self, pane: &str, keys: &str) -> Result<()> {
+        (**self).send_keys(pane, keys).await
+    }
+
+    async fn send_text(&self, pane: &str, text: &str) -> Result<()> {
+        (**self).send_text(pane, text).await
+    }
+
+    async fn paste_text(&self, pane: &str, text: &str) -> Result<()> {
+        (**self).paste_text(pane, text).await
+    }
+
+    async fn check_pane_exists(&self, pane: &str) -> Result<bool> {
+        (**self).check_pane_exists(pane).await
+    }
+
+    async fn pane_metadata(&self, pane: &str) -> Result<PaneMetadata> {
+        (**self).pane_metadata(pane).await
+    }
+
+    async fn resolve_pane(&self, target: &str) -> Result<String> {
+        (**self).resolve_pane(target).await
+    }
+
+    async fn pane_fingerprint(&self, pane: &str) -> Result<Option<PaneFingerprint>> {
+        (**self).pane_fingerprint(pane).await
+    }
+
+    async fn respawn_pane(&self, pane: &str, command: &str) -> Result<()> {
+        (**self).respawn_pane(pane, command).await
+    }
+}
+
+/// 内存中的脚本化后端，用于在没有 tmux 的情况下测试监控循环
+#[cfg(test)]
+pub mod scripted {
+    use super::*;
+    use crate::intervention::Intervention;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    /// 每次捕获依次返回预设的画面，画面用完后一直返回最后一帧
+    pub struct ScriptedBackend {
+        frames: Mutex<VecDeque<String>>,
+        last_frame: Mutex<String>,
+        sent: Mutex<Vec<(String, Intervention)>>,
+        respawned: Mutex<Vec<String>>,
+        exists: AtomicBool,
+        pub metadata: PaneMetadata,
+    }
+
+    impl ScriptedBackend {
+        pub fn new<I, S>(frames: I) -> Self
+        where
+            I: IntoIterator<Item = S>,
+            S: Into<String>,
+        {
+            ScriptedBackend {
+                frames: Mutex::new(frames.into_iter().map(Into::into).collect()),
+                last_frame: Mutex::new(String::new()),
+                sent: Mutex::new(Vec::new()),
+                respawned: Mutex::new(Vec::new()),
+                exists: AtomicBool::new(true),
+                metadata: PaneMetadata::default(),
+            }
+        }
+
+        /// 设置后续 pane 是否存在
+        pub fn set_exists(&self, exists: bool) {
+            self.exists.store(exists, Ordering::SeqCst);
+        }
+
+        /// 已发送的 (pane, 输入) 记录
+        pub fn sent(&self) -> Vec<(String, Intervention)> {
+            self.sent.lock().unwrap().clone()
+        }
+
+        /// 通过 respawn_pane 执行过的命令
+        pub fn respawned(&self) -> Vec<String> {
+            self.respawned.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl PaneBackend for ScriptedBackend {
+        async fn capture_pane_content(&self, _pane: &str) -> Result<String> {
+            let mut last = self.last_frame.lock().unwrap();
+            if let Some(frame) = self.frames.lock().unwrap().pop_front() {
+                *last = frame;
+            }
+            Ok(last.clone())
+        }
+
+        async fn send_keys(&self, pane: &str, keys: &str) -> Result<()> {
+            self.sent.lock().unwrap().push((pane.to_string(), Intervention::Key(keys.to_string())));
+            Ok(())
+        }
+
+        async fn send_text(&self, pane: &str, text: &str) -> Result<()> {
+            self.sent.lock().unwrap().push((pane.to_string(), Intervention::Text(text.to_string())));
+            Ok(())
+        }
+
+        async fn paste_text(&self, pane: &str, text: &str) -> Result<()> {
+            self.sent.lock().unwrap().push((pane.to_string(), Intervention::Paste(text.to_string())));
+            Ok(())
+        }
+
+        async fn check_pane_exists(&self, _pane: &str) -> Result<bool> {
+            Ok(self.exists.load(Ordering::SeqCst))
+        }
+
+        async fn pane_metadata(&self, pane: &str) -> Result<PaneMetadata> {
+            Ok(PaneMetadata {
+                pane_id: pane.to_string(),
+                ..self.metadata.clone()
+            })
+        }
+
+        async fn respawn_pane(&self, _pane: &str, command: &str) -> Result<()> {
+            self.respawned.lock().unwrap().push(command.to_string());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use crate::activity::ActivityResult;
+use crate::config::StateConfig;
+use crate::liveness::AgentExit;
+use crate::profile::IdlePrompts;
+
+/// 从屏幕内容判断出的 agent 状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentState {
+    Working,             // 活动标识匹配，正在工作
+    IdleAtPrompt,        // 停在输入提示符上等待指令（未配置输入提示符时，无法识别的空闲也归为此类）
+    AwaitingPermission,  // 等待用户确认权限或操作
+    RateLimited,         // 遇到 API 限流或额度用尽
+    ContextFull,         // 上下文已满
+    ErrorShown,          // 屏幕上显示了错误
+    CrashedToShell,      // agent 已退出，回到了 shell
+    Finished,            // agent 报告任务已完成
+    Unknown,             // 配置了输入提示符但没有看到，也没有匹配其它状态
+}
+
+impl AgentState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AgentState::Working => "工作中",
+            AgentState::IdleAtPrompt => "等待输入",
+            AgentState::AwaitingPermission => "等待权限确认",
+            AgentState::RateLimited => "被限流",
+            AgentState::ContextFull => "上下文已满",
+            AgentState::ErrorShown => "出现错误",
+            AgentState::CrashedToShell => "已退出到 shell",
+            AgentState::Finished => "任务已完成",
+            AgentState::Unknown => "未知",
+        }
+    }
+}
+
+impl fmt::Display for AgentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// 一次分类的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classification {
+    pub state: AgentState,
+    pub evidence: Option<String>,  // 判定依据：匹配的屏幕行、活动标识或退出迹象
+}
+
+/// agent 状态分类器
+/// 依次判断：已退出、活动标识、屏幕末尾的权限确认 / 限流 / 上下文已满 / 错误 / 完成提示，最后看输入提示符
+pub struct StateClassifier {
+    patterns: Vec<(AgentState, Vec<Regex>)>,
+    search_lines: usize,
+    idle_prompts: IdlePrompts,
+}
+
+impl StateClassifier {
+    /// 无效的正则会被忽略并给出警告
+    pub fn new(config: &StateConfig, idle_prompts: &[String]) -> Self {
+        let compile = |patterns: &[String]| -> Vec<Regex> {
+            patterns
+                .iter()
+                .filter_map(|pattern| match RegexBuilder::new(pattern).case_insensitive(true).build() {
+                    Ok(regex) => Some(regex),
+                    Err(e) => {
+                        eprintln!("⚠️ 忽略无效的状态正则 {:?}: {}", pattern, e);
+                        None
+                    }
+                })
+                .collect()
+        };
+        // 顺序即优先级：对话框和限流提示比残留的错误信息更能说明当前状态
+        let patterns = vec![
+            (AgentState::AwaitingPermission, compile(&config.awaiting_permission)),
+            (AgentState::RateLimited, compile(&config.rate_limited)),
+            (AgentState::ContextFull, compile(&config.context_full)),
+            (AgentState::ErrorShown, compile(&config.error_shown)),
+            (AgentState::Finished, compile(&config.finished)),
+        ];
+        StateClassifier {
+            patterns,
+            search_lines: config.search_lines,
+            idle_prompts: IdlePrompts::new(idle_prompts),
+        }
+    }
+
+    pub fn classify(&self, result: &ActivityResult, exit: Option<&AgentExit>) -> Classification {
+        if let Some(exit) = exit {
+            return Classification { state: AgentState::CrashedToShell, evidence: Some(exit.describe()) };
+        }
+        if result.is_active {
+            return Classification { state: AgentState::Working, evidence: Some(result.indicators.positive.join(", ")) };
+        }
+
+        // 只看屏幕末尾，较早的输出中出现的错误或提示已经过时
+        let tail: Vec<&str> = result
+            .last_content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let tail = &tail[tail.len().saturating_sub(self.search_lines)..];
+        for (state, regexes) in &self.patterns {
+            if let Some(line) = tail.iter().rev().find(|line| regexes.iter().any(|regex| regex.is_match(line))) {
+                return Classification { state: *state, evidence: Some(line.to_string()) };
+            }
+        }
+
+        if self.idle_prompts.is_enabled() && !self.idle_prompts.visible(&result.last_content) {
+            return Classification { state: AgentState::Unknown, evidence: None };
+        }
+        Classification { state: AgentState::IdleAtPrompt, evidence: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ansi::StyledScreen;
+    use crate::indicators::IndicatorMatch;
+
+    fn result(content: &str, is_active: bool) -> ActivityResult {
+        ActivityResult {
+            is_active,
+            indicators: IndicatorMatch { positive: if is_active { vec!["working".to_string()] } else { Vec::new() }, negative: None },
+            content_changed: false,
+            fingerprint: 0,
+            last_content: content.to_string(),
+            screen: StyledScreen::default(),
+            output_rate: None,
+        }
+    }
+
+    fn state(classifier: &StateClassifier, content: &str) -> AgentState {
+        classifier.classify(&result(content, false), None).state
+    }
+
+    #[test]
+    fn test_builtin_states() {
+        let classifier = StateClassifier::new(&StateConfig::default(), &[]);
+        assert_eq!(classifier.classify(&result("Error: boom\nworking", true), None).state, AgentState::Working);
+        assert_eq!(state(&classifier, "Bash(rm -rf build)\nDo you want to proceed?\n❯ 1. Yes\n  2. No"), AgentState::AwaitingPermission);
+        assert_eq!(state(&classifier, "Allow edits to src/main.rs? (Y)es/(N)o [Yes]:"), AgentState::AwaitingPermission);
+        assert_eq!(state(&classifier, "API Error: 429 rate_limit_error · Retrying in 30 seconds"), AgentState::RateLimited);
+        assert_eq!(state(&classifier, "Claude usage limit reached. Your limit will reset at 5pm"), AgentState::RateLimited);
+        assert_eq!(state(&classifier, "Error: prompt is too long: 210000 tokens > 200000 maximum"), AgentState::ContextFull);
+        assert_eq!(state(&classifier, "API Error: Connection error.\n> "), AgentState::ErrorShown);
+        assert_eq!(state(&classifier, "All tasks completed. The parser now handles comments.\n> "), AgentState::Finished);
+        assert_eq!(state(&classifier, "> "), AgentState::IdleAtPrompt);
+    }
+
+    #[test]
+    fn test_exit_wins() {
+        let classifier = StateClassifier::new(&StateConfig::default(), &[]);
+        let classification = classifier.classify(&result("working", true), Some(&AgentExit::PaneDead));
+        assert_eq!(classification.state, AgentState::CrashedToShell);
+    }
+
+    #[test]
+    fn test_only_screen_tail_counts() {
+        let config = StateConfig { search_lines: 3, ..StateConfig::default() };
+        let classifier = StateClassifier::new(&config, &[]);
+        let content = format!("Error: old failure\n{}> ", "fixed it\n".repeat(3));
+        assert_eq!(state(&classifier, &content), AgentState::IdleAtPrompt);
+        let classification = classifier.classify(&result("ok\nError: new failure\n> ", false), None);
+        assert_eq!(classification.evidence.as_deref(), Some("Error: new failure"));
+    }
+
+    #[test]
+    fn test_idle_prompt_required_when_configured() {
+        let classifier = StateClassifier::new(&StateConfig::default(), &[r"^> ?$".to_string()]);
+        assert_eq!(state(&classifier, "compiling..."), AgentState::Unknown);
+        assert_eq!(state(&classifier, "done\n> "), AgentState::IdleAtPrompt);
+    }
+
+    #[test]
+    fn test_state_keys_in_yaml() {
+        let parsed: std::collections::HashMap<AgentState, Vec<String>> =
+            serde_yaml::from_str("context_full: [\"/compact\"]\nawaiting_permission: []").unwrap();
+        assert_eq!(parsed[&AgentState::ContextFull], vec!["/compact".to_string()]);
+        assert!(parsed[&AgentState::AwaitingPermission].is_empty());
+    }
+}
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use crate::classify::AgentState;
 use lazy_static::lazy_static;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,75 +10,784 @@ pub struct Config {
     pub tmux: TmuxConfig,
     pub monitoring: MonitoringConfig,
     pub intervention: InterventionConfig,
+    #[serde(default)]
+    pub respawn: RespawnConfig,  // agent 退出后的处理
+    #[serde(default)]
+    pub normalize: NormalizeConfig,  // 比较屏幕变化前的规范化处理
+    #[serde(default)]
+    pub states: StateConfig,  // 识别 agent 状态的屏幕提示
+    #[serde(default)]
+    pub approval: ApprovalConfig,  // 权限确认对话框的自动处理
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,  // 限流和服务端错误后的等待与恢复
+    #[serde(default)]
+    pub loop_detection: LoopConfig,  // 识别 agent 反复执行同样操作的循环
+    #[serde(default)]
+    pub usage: UsageConfig,  // 从屏幕读取 token、费用和上下文占用，超出预算时停止干预
+    #[serde(default)]
+    pub llm: LlmConfig,  // 卡住时请 LLM 分析 pane 内容
+    #[serde(default)]
+    pub profile: Option<ProfileKind>,  // 内置 agent 配置档，未配置时为 opencode
+    #[serde(default)]
+    pub indicators: Vec<IndicatorRule>,  // 判断 agent 正在工作的屏幕标识，为空时使用配置档
+    #[serde(default)]
+    pub regions: HashMap<String, ScreenRegion>,  // 命名的屏幕区域，可在规则中按名称引用；与内置的 status_bar、input_box 同名时替换内置定义
+    #[serde(default)]
+    pub idle_prompts: Option<Vec<String>>,  // agent 等待输入时的提示符正则，未配置时使用配置档，[] 表示不确认
+    #[serde(default)]
+    pub targets: Vec<TargetConfig>,  // 同时监控的多个目标；为空时只监控 tmux.pane
+}
+
+/// 单个监控目标
+/// monitoring / intervention 未配置时继承全局设置，配置时整体替换对应段
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetConfig {
+    #[serde(default)]
+    pub name: Option<String>,  // 日志中显示的名称，默认使用 pane
+    pub pane: String,
+    #[serde(default)]
+    pub backend: Option<BackendKind>,
+    #[serde(default)]
+    pub profile: Option<ProfileKind>,  // 该目标运行的 agent，未配置时继承全局 profile
+    #[serde(default)]
+    pub monitoring: Option<MonitoringConfig>,
+    #[serde(default)]
+    pub intervention: Option<InterventionConfig>,
+    #[serde(default)]
+    pub respawn: Option<RespawnConfig>,
+    #[serde(default)]
+    pub normalize: Option<NormalizeConfig>,
+    #[serde(default)]
+    pub states: Option<StateConfig>,
+    #[serde(default)]
+    pub approval: Option<ApprovalConfig>,  // 该项目的审批规则，配置时整体替换全局设置
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    #[serde(default)]
+    pub loop_detection: Option<LoopConfig>,
+    #[serde(default)]
+    pub usage: Option<UsageConfig>,  // 该目标的用量解析和预算，配置时整体替换全局设置
+    #[serde(default)]
+    pub indicators: Option<Vec<IndicatorRule>>,
+    #[serde(default)]
+    pub idle_prompts: Option<Vec<String>>,
+    #[serde(default)]
+    pub remote: Option<RemoteConfig>,  // 目标所在的远程主机，未配置时继承 tmux.remote
+    #[serde(default)]
+    pub socket_name: Option<String>,  // 目标所在的 tmux 服务器，配置任意一项时替换 tmux 中的两项
+    #[serde(default)]
+    pub socket_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TmuxConfig {
     pub pane: String,
+    #[serde(default)]
+    pub backend: BackendKind,  // 终端复用器类型；screen 时 pane 格式为 会话[:窗口]
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub agent_commands: Vec<String>,  // 自动发现时识别为 agent 的程序名（pane 设为 auto 时启用发现），为空时使用配置档
+    #[serde(default)]
+    pub remote: Option<RemoteConfig>,  // 通过 SSH 监控远程主机上的 tmux，未配置时使用本机 tmux
+    #[serde(default = "default_command_timeout")]
+    pub command_timeout_ms: u64,  // 单次 tmux 命令的超时（毫秒），超时视为暂时不可用
+    #[serde(default)]
+    pub socket_name: Option<String>,  // 连接指定名称的 tmux 服务器（tmux -L）
+    #[serde(default)]
+    pub socket_path: Option<String>,  // 连接指定路径的 tmux socket（tmux -S），优先于 socket_name；远程时为远程主机上的路径
+}
+
+/// 远程主机（SSH）配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    pub host: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub identity_file: Option<String>,  // 私钥路径（ssh -i）
+    #[serde(default)]
+    pub tmux_socket: Option<String>,    // 远程主机上的 tmux socket 路径（tmux -S），与 tmux.socket_path 相同，保留以兼容旧配置
+    #[serde(default = "default_control_persist")]
+    pub control_persist_sec: u64,       // 复用的主连接在空闲多久后关闭（ControlPersist）
+}
+
+/// 终端后端类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Tmux,
+    Screen,
+}
+
+/// 内置 agent 配置档，各档的具体内容见 profile.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfileKind {
+    #[default]
+    Opencode,
+    Claude,
+    Aider,
+    Codex,
+    GeminiCli,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CaptureConfig {
+    #[serde(default)]
+    pub scrollback: u32,      // 额外捕获的滚动历史行数（capture-pane -S -N），0 表示只捕获可见屏幕
+    #[serde(default)]
+    pub escapes: bool,        // 保留颜色等转义序列（capture-pane -e）并解析为带样式的单元格
+    #[serde(default)]
+    pub join_lines: bool,     // 合并自动换行的行（capture-pane -J）
+    #[serde(default)]
+    pub pipe_pane: bool,      // 用 pipe-pane 持续记录原始输出，由内置虚拟终端还原屏幕（仅 tmux）
+    #[serde(default)]
+    pub log_dir: Option<String>,  // pipe-pane 原始输出日志目录，默认 opencode-watch-logs
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonitoringConfig {
     pub interval: u64,
     pub stuck_sec: u64,
-    pub max_retry: usize,
+    pub max_retry: usize,  // 连续干预次数上限，agent 恢复工作后清零
     #[serde(default = "default_long_stuck_sec")]
     pub long_stuck_sec: u64,  // 第二阶段：长时间无变化判定时间
+    #[serde(default)]
+    pub control_mode: bool,   // 使用 tmux 控制模式接收输出通知，而不是单纯轮询
+    #[serde(default = "default_control_debounce")]
+    pub control_debounce_ms: u64,  // 收到输出后合并连续输出的等待时间
+    #[serde(default)]
+    pub tmux_hooks: bool,     // 在 pane 上安装 tmux hook，由 tmux 推送静默和退出事件
+    #[serde(default = "default_batch_poll")]
+    pub batch_poll: bool,     // 同一 tmux 服务器上的目标共用一次 list-panes -a，活动时间未变化的 pane 不重新捕获
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InterventionConfig {
-    #[serde(default = "default_commands")]
-    pub commands: Vec<String>,
+    #[serde(default)]
+    pub commands: Vec<String>,  // 为空时使用配置档中的指令
+    #[serde(default)]
+    pub on_state: HashMap<AgentState, Vec<String>>,  // 各状态使用的指令，空列表表示该状态不干预；未列出的状态见 commands_for
     #[serde(default = "default_command_delay")]
     pub command_delay_ms: u64,
     #[serde(default = "default_enter_delay")]
     pub enter_delay_ms: u64,
 }
 
+/// 活动标识规则
+/// 任一正向规则匹配且没有反向规则匹配时，判定 agent 正在工作
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndicatorRule {
+    #[serde(default)]
+    pub name: Option<String>,      // 日志中显示的名称，默认使用 pattern
+    pub pattern: String,
+    #[serde(default)]
+    pub regex: bool,               // 按正则匹配，默认按字面包含
+    #[serde(default)]
+    pub ignore_case: bool,
+    #[serde(default)]
+    pub negative: bool,            // 反向规则：出现时判定为不在工作，优先于正向规则
+    #[serde(default)]
+    pub region: Option<RegionRef>,  // 只在屏幕的这部分查找，默认整个可见屏幕
+}
+
+impl IndicatorRule {
+    /// 按字面包含匹配的正向规则
+    pub fn literal(pattern: &str) -> Self {
+        IndicatorRule {
+            name: None,
+            pattern: pattern.to_string(),
+            regex: false,
+            ignore_case: false,
+            negative: false,
+            region: None,
+        }
+    }
+}
+
+/// 屏幕区域，行号不计末尾的空行
+/// 依次应用：锚点截取、行范围、first_lines / last_lines
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScreenRegion {
+    #[serde(default)]
+    pub first_lines: Option<usize>,  // 最上面 N 行
+    #[serde(default)]
+    pub last_lines: Option<usize>,   // 最下面 N 行（例如状态栏）
+    #[serde(default)]
+    pub start_row: Option<isize>,    // 起始行（含），从 0 开始，负数从末尾倒数（-1 为最后一行）
+    #[serde(default)]
+    pub end_row: Option<isize>,      // 结束行（含），规则同 start_row
+    #[serde(default)]
+    pub start_anchor: Option<String>,  // 从最后一个匹配该正则的行开始（含该行），没有匹配时区域为空
+    #[serde(default)]
+    pub end_anchor: Option<String>,    // 到起始行之后第一个匹配该正则的行结束（含该行），没有匹配时到末尾
+}
+
+/// 规则引用的屏幕区域：regions 中定义（或内置）的名称，或直接写出的区域
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RegionRef {
+    Named(String),
+    Inline(ScreenRegion),
+}
+
+/// agent 退出（pane 死亡、回到 shell）后的处理
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RespawnConfig {
+    #[serde(default)]
+    pub command: Option<String>,  // 重新启动并恢复会话的命令，例如 "opencode --continue"；未配置时检测到退出只报告并结束监控
+    #[serde(default)]
+    pub method: RespawnMethod,
+    #[serde(default = "default_respawn_attempts")]
+    pub max_attempts: usize,      // 连续重启的最大次数，agent 恢复工作后清零
+    #[serde(default = "default_respawn_grace")]
+    pub grace_sec: u64,           // 重启后多少秒内不再检测退出，等待 agent 启动
+    #[serde(default = "default_shell_prompts")]
+    pub shell_prompts: Vec<String>,  // 识别 shell 提示符的正则，匹配屏幕最后一个非空行
+}
+
+/// 重新启动 agent 的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RespawnMethod {
+    #[default]
+    Type,         // 在 pane 的 shell 中输入命令并回车；pane 已死亡时改用 respawn-pane
+    RespawnPane,  // 总是用 tmux respawn-pane -k 替换 pane 中的进程
+}
+
+impl Default for RespawnConfig {
+    fn default() -> Self {
+        RespawnConfig {
+            command: None,
+            method: RespawnMethod::Type,
+            max_attempts: default_respawn_attempts(),
+            grace_sec: default_respawn_grace(),
+            shell_prompts: default_shell_prompts(),
+        }
+    }
+}
+
+/// 权限确认对话框的自动处理
+/// 规则以 `re:` 开头时按正则查找，其余按 glob 完整匹配；拒绝规则优先，都不匹配时交给人工
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub allow_commands: Vec<String>,  // 自动批准的命令，例如 "cargo *"
+    #[serde(default)]
+    pub deny_commands: Vec<String>,   // 自动拒绝的命令，例如 "git push*"
+    #[serde(default)]
+    pub allow_paths: Vec<String>,     // 自动批准修改的文件，* 不跨越目录，** 匹配任意层目录，不含 / 的规则也匹配文件名
+    #[serde(default)]
+    pub deny_paths: Vec<String>,
+    #[serde(default = "default_request_patterns")]
+    pub request_patterns: Vec<String>,  // 从对话框中提取请求的正则，命名分组 command 或 path
+    #[serde(default)]
+    pub approve_keys: Vec<String>,    // 批准时发送的按键或文本（干预指令格式），为空时使用配置档
+    #[serde(default)]
+    pub reject_keys: Vec<String>,     // 拒绝时发送的按键或文本，为空时使用配置档
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        ApprovalConfig {
+            enabled: false,
+            allow_commands: Vec::new(),
+            deny_commands: Vec::new(),
+            allow_paths: Vec::new(),
+            deny_paths: Vec::new(),
+            request_patterns: default_request_patterns(),
+            approve_keys: Vec::new(),
+            reject_keys: Vec::new(),
+        }
+    }
+}
+
+/// 被限流或遇到服务端错误（rate_limited 状态）后的处理
+/// 从屏幕提示中解析建议的等待时间，期间暂停干预，到时后发送恢复指令
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,  // 关闭时 rate_limited 按 on_state 处理（默认不干预）
+    #[serde(default = "default_retry_patterns")]
+    pub retry_patterns: Vec<String>,  // 解析等待时间的正则（忽略大小写），命名分组 delay（时长，如 42s、1m30s）或 clock（本地时刻，如 5pm、17:30）
+    #[serde(default = "default_retry_delay")]
+    pub default_delay_sec: u64,  // 没有解析出等待时间时的等待秒数
+    #[serde(default = "default_max_retry_delay")]
+    pub max_delay_sec: u64,      // 等待时间上限
+    #[serde(default = "default_retry_margin")]
+    pub margin_sec: u64,         // 在解析出的时间之外多等待的秒数
+    #[serde(default = "default_resume_prompt")]
+    pub resume_prompt: String,   // 等待结束后发送的指令（干预指令格式）
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            retry_patterns: default_retry_patterns(),
+            default_delay_sec: default_retry_delay(),
+            max_delay_sec: default_max_retry_delay(),
+            margin_sec: default_retry_margin(),
+            resume_prompt: default_resume_prompt(),
+        }
+    }
+}
+
+/// agent 循环检测
+/// 屏幕在变化、活动标识也在，但 agent 可能在反复做同一次修改或重跑同一个失败的命令
+/// 在 window_sec 内统计规范化后的屏幕指纹和屏幕上新出现的命令 / 修改行，重复次数达到阈值时发送专门的指令
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoopConfig {
+    #[serde(default)]
+    pub enabled: bool,          // 默认关闭：修改 → 测试 → 修改这样正常的往复也会让屏幕重复出现，需要按 agent 调好阈值后再开启
+    #[serde(default = "default_loop_window")]
+    pub window_sec: u64,        // 统计窗口
+    #[serde(default = "default_screen_repeats")]
+    pub screen_repeats: usize,  // 同一屏幕（规范化后的可见内容）在窗口内再次出现的次数
+    #[serde(default = "default_action_repeats")]
+    pub action_repeats: usize,  // 同一命令或修改在窗口内出现的次数
+    #[serde(default = "default_action_patterns")]
+    pub action_patterns: Vec<String>,  // 识别命令 / 修改行的正则（多行模式），命名分组 action 为比较的内容，没有时取整个匹配
+    #[serde(default = "default_loop_commands")]
+    pub commands: Vec<String>,  // 检测到循环时依次发送的指令（干预指令格式），可以在前面加 "Escape" 先打断 agent
+}
+
+impl Default for LoopConfig {
+    fn default() -> Self {
+        LoopConfig {
+            enabled: false,
+            window_sec: default_loop_window(),
+            screen_repeats: default_screen_repeats(),
+            action_repeats: default_action_repeats(),
+            action_patterns: default_action_patterns(),
+            commands: default_loop_commands(),
+        }
+    }
+}
+
+/// 判定卡住时把 pane 内容交给 LLM 分析，提示语随 agent 配置档变化；分析结果只记录在日志中，不影响干预
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LlmConfig {
+    #[serde(default = "default_llm_backend")]
+    pub backend: String,  // ollama、openai、openrouter 或 none（不分析）
+    #[serde(default = "default_llm_model")]
+    pub model: String,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        LlmConfig { backend: default_llm_backend(), model: default_llm_model() }
+    }
+}
+
+/// token、费用和上下文占用的解析与预算
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_usage_patterns")]
+    pub patterns: Vec<String>,  // 解析用量的正则（忽略大小写），命名分组 tokens、cost、context（已用百分比）或 context_left（剩余百分比）；同一项按顺序取第一个匹配的正则
+    #[serde(default = "default_usage_search_lines")]
+    pub search_lines: usize,    // 只在屏幕末尾这么多个非空行（状态栏）中查找，0 表示整个可见屏幕（从下往上）
+    #[serde(default = "default_usage_history")]
+    pub history: usize,         // 保留的采样个数
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub on_exceeded: BudgetAction,
+    #[serde(default = "default_wrap_up_prompt")]
+    pub wrap_up_prompt: String,       // on_exceeded 为 wrap-up 时发送的指令
+    #[serde(default = "default_interrupt_keys")]
+    pub interrupt_keys: Vec<String>,  // on_exceeded 为 interrupt 时依次发送的按键
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            enabled: true,
+            patterns: default_usage_patterns(),
+            search_lines: default_usage_search_lines(),
+            history: default_usage_history(),
+            budget: BudgetConfig::default(),
+            on_exceeded: BudgetAction::default(),
+            wrap_up_prompt: default_wrap_up_prompt(),
+            interrupt_keys: default_interrupt_keys(),
+        }
+    }
+}
+
+/// 用量预算，未配置的项不限制
+/// 会话用量取 agent 显示的累计值；每日用量累加各次采样的增量（累计值连续两次变小或重启后变小时从新的累计值开始），本地零点清零
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    #[serde(default)]
+    pub session_tokens: Option<u64>,
+    #[serde(default)]
+    pub session_cost: Option<f64>,  // 美元
+    #[serde(default)]
+    pub daily_tokens: Option<u64>,
+    #[serde(default)]
+    pub daily_cost: Option<f64>,
+}
+
+/// 超出预算后的处理，之后都暂停该目标的干预，直到不再超出（例如过了零点）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetAction {
+    #[default]
+    Pause,      // 只暂停干预
+    WrapUp,     // 发送收尾指令，让 agent 保存进度后停下
+    Interrupt,  // 发送 interrupt_keys 打断 agent
+}
+
+/// 识别 agent 状态的屏幕提示，均为忽略大小写的正则，只在屏幕末尾 search_lines 个非空行中查找
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateConfig {
+    #[serde(default = "default_search_lines")]
+    pub search_lines: usize,
+    #[serde(default = "default_permission_patterns")]
+    pub awaiting_permission: Vec<String>,  // 权限确认对话框
+    #[serde(default = "default_rate_limit_patterns")]
+    pub rate_limited: Vec<String>,         // 限流、额度用尽
+    #[serde(default = "default_context_full_patterns")]
+    pub context_full: Vec<String>,         // 上下文已满
+    #[serde(default = "default_error_patterns")]
+    pub error_shown: Vec<String>,          // 错误信息
+    #[serde(default = "default_finished_patterns")]
+    pub finished: Vec<String>,             // 任务完成
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        StateConfig {
+            search_lines: default_search_lines(),
+            awaiting_permission: default_permission_patterns(),
+            rate_limited: default_rate_limit_patterns(),
+            context_full: default_context_full_patterns(),
+            error_shown: default_error_patterns(),
+            finished: default_finished_patterns(),
+        }
+    }
+}
+
+/// 比较屏幕变化前的规范化处理
+/// spinner、计时器和时钟每次刷新都会变化，pane 尺寸变化会改变换行位置，规范化后再计算指纹比较
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NormalizeConfig {
+    #[serde(default = "default_masks")]
+    pub masks: Vec<String>,       // 这些正则匹配的内容在比较前替换为占位符，默认屏蔽时钟和耗时
+    #[serde(default = "default_true")]
+    pub strip_spinners: bool,     // 去掉 spinner 字符（盲文点阵、✻ ◐ 等）
+    #[serde(default = "default_true")]
+    pub fold_whitespace: bool,    // 连续空白合并为一个空格，去掉空行
+    #[serde(default = "default_true")]
+    pub reflow: bool,             // 忽略换行位置和边框线长度，pane 宽度变化不算内容变化
+}
+
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        NormalizeConfig {
+            masks: default_masks(),
+            strip_spinners: true,
+            fold_whitespace: true,
+            reflow: true,
+        }
+    }
+}
+
+/// 程序名、活动标识、输入提示符、干预指令和审批按键留空，由 apply_profile 按所选配置档补全
 impl Default for Config {
     fn default() -> Self {
         Config {
             tmux: TmuxConfig {
                 pane: "%18".to_string(),
+                backend: BackendKind::Tmux,
+                capture: CaptureConfig::default(),
+                agent_commands: Vec::new(),
+                remote: None,
+                command_timeout_ms: default_command_timeout(),
+                socket_name: None,
+                socket_path: None,
             },
             monitoring: MonitoringConfig {
                 interval: 5,
                 stuck_sec: 30,
                 long_stuck_sec: 120, // 2分钟
                 max_retry: 6, // 增加到6次以支持完整的命令循环
+                control_mode: false,
+                control_debounce_ms: 200,
+                tmux_hooks: false,
+                batch_poll: true,
             },
             intervention: InterventionConfig {
-                commands: vec![
-                    "继续".to_string(),
-                    "继续工作".to_string(),
-                    "请继续".to_string(),
-                    "/compact".to_string(),
-                    "C-c".to_string(),
-                    "请继续工作".to_string(),
-                ],
+                commands: Vec::new(),
+                on_state: HashMap::new(),
                 command_delay_ms: 1000,
                 enter_delay_ms: 3000,
             },
+            respawn: RespawnConfig::default(),
+            normalize: NormalizeConfig::default(),
+            states: StateConfig::default(),
+            approval: ApprovalConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            loop_detection: LoopConfig::default(),
+            usage: UsageConfig::default(),
+            llm: LlmConfig::default(),
+            profile: None,
+            indicators: Vec::new(),
+            regions: HashMap::new(),
+            idle_prompts: None,
+            targets: Vec::new(),
         }
     }
 }
 
+// 默认 tmux 命令超时（毫秒）
+fn default_command_timeout() -> u64 {
+    5000
+}
+
+// 默认 SSH 主连接保持时间（秒）
+fn default_control_persist() -> u64 {
+    600
+}
+
+// 默认连续重启次数上限
+fn default_respawn_attempts() -> usize {
+    3
+}
+
+// 默认重启后的等待时间（秒）
+fn default_respawn_grace() -> u64 {
+    15
+}
+
+// 默认 shell 提示符：user@host:dir$、[user@host dir]$、bash-5.2$
+fn default_shell_prompts() -> Vec<String> {
+    vec![
+        r"^[\w.-]+@[\w.-]+[: ].*[$#%]$".to_string(),
+        r"^\[[\w.-]+@[\w.-]+ [^\]]*\][$#]$".to_string(),
+        r"^(bash|sh|zsh|dash)-[\d.]+[$#]$".to_string(),
+    ]
+}
+
+// 默认识别的权限请求：Claude Code 的 Bash command 方框和 Bash(...) / Edit(...)、aider 的 Run shell command? 和 Allow edits to
+fn default_request_patterns() -> Vec<String> {
+    vec![
+        // 方框中命令可能折成多行，取到 "Do you want" 之前的全部内容（包括下方的说明行）
+        r"^(?:Bash|Shell) command\s*\n\s*(?P<command>\S.*(?:\s*\n.*\S.*)*?)\s*\n\s*Do you want".to_string(),
+        r"^(?:Bash|Shell|Run|Execute)\((?P<command>.+)\)$".to_string(),
+        r"^(?P<command>.+)\nRun shell commands?\?".to_string(),
+        r"^\$ (?P<command>.+)$".to_string(),
+        r"^(?:Edit|Write|Update|Create|MultiEdit|Delete)\((?P<path>[^)]+)\)$".to_string(),
+        r"(?:edits?|changes) to (?P<path>[^\s?]+)".to_string(),
+        r"(?:create|overwrite|write to|delete) (?P<path>[^\s?]+)\?".to_string(),
+    ]
+}
+
+// 默认在屏幕末尾 15 个非空行中识别状态
+fn default_search_lines() -> usize {
+    15
+}
+
+// 默认权限确认提示：Claude Code、aider、codex 等的确认对话框
+fn default_permission_patterns() -> Vec<String> {
+    vec![
+        r"do you want to (proceed|make this edit|create|run|allow)".to_string(),
+        r"\(Y\)es/\(N\)o".to_string(),
+        r"[\[(]y/n[\])]".to_string(),
+        r"\ballow (this|once|always)\b".to_string(),
+        r"permission required".to_string(),
+        r"waiting for (your )?(approval|confirmation)".to_string(),
+    ]
+}
+
+// 默认限流提示
+fn default_rate_limit_patterns() -> Vec<String> {
+    vec![
+        r"rate.?limit".to_string(),
+        r"too many requests".to_string(),
+        r"\b429\b".to_string(),
+        r"usage limit".to_string(),
+        r"quota (exceeded|exhausted)".to_string(),
+        r"resource.?exhausted".to_string(),
+        r"\boverloaded".to_string(),
+        r"\b(502|503|529)\b".to_string(),
+        r"service (temporarily )?unavailable".to_string(),
+        r"server (is )?(busy|overloaded)".to_string(),
+    ]
+}
+
+// 默认识别的等待时间："retry in 42s"、"try again in 1m30s"、"Retry-After: 120"、"retryDelay": "27s"、"resets 5pm"
+fn default_retry_patterns() -> Vec<String> {
+    let unit = r"(?:milliseconds?|ms|seconds?|secs?|s|minutes?|mins?|m|hours?|hrs?|h|days?|d)";
+    vec![
+        format!(r"(?:retry|retrying|try again|wait|available)\s+(?:in|after)\s+(?P<delay>(?:\d+(?:\.\d+)?\s*{}?[\s,]*(?:and\s+)?)+)", unit),
+        r"retry-after:?\s*(?P<delay>\d+)".to_string(),
+        r#""retryDelay":\s*"(?P<delay>[\d.]+s)""#.to_string(),
+        r"(?:resets?|try again|available again)\s+(?:at\s+)?(?P<clock>\d{1,2}:\d{2}(?:\s*[ap]m)?|\d{1,2}\s*[ap]m)".to_string(),
+    ]
+}
+
+// 没有解析出等待时间时默认等待 60 秒
+fn default_retry_delay() -> u64 {
+    60
+}
+
+// 等待时间上限 6 小时（订阅额度通常按 5 小时窗口重置）
+fn default_max_retry_delay() -> u64 {
+    6 * 3600
+}
+
+// 默认多等待 5 秒
+fn default_retry_margin() -> u64 {
+    5
+}
+
+fn default_resume_prompt() -> String {
+    "继续".to_string()
+}
+
+// 默认在最近 15 分钟内统计重复
+fn default_loop_window() -> u64 {
+    900
+}
+
+// 默认同一画面出现 5 次才算循环，修改 → 测试的往复通常不会在 15 分钟内回到完全相同的画面这么多次
+fn default_screen_repeats() -> usize {
+    5
+}
+
+// 反复运行测试是正常的工作方式，命令重复的阈值比屏幕重复高
+fn default_action_repeats() -> usize {
+    5
+}
+
+// 默认识别的命令和修改行：Claude Code 的 ● Bash(...) / Update(...)、opencode 的 | Bash ...、aider 的 Applied edit to ...、codex 的 Ran ...、shell 的 $ ...
+fn default_action_patterns() -> Vec<String> {
+    vec![
+        r"^[●⏺]\s*(?P<action>(?:Bash|Update|Edit|MultiEdit|Write)\(.+\))\s*$".to_string(),
+        r"^[|┃]\s*(?P<action>(?:Bash|Edit|Write|Patch)\s+\S.*)$".to_string(),
+        r"^(?P<action>Applied edit to \S+)".to_string(),
+        r"^[•⚡✓]?\s*(?P<action>Ran \S.*)$".to_string(),
+        r"^\$ (?P<action>\S.*)$".to_string(),
+    ]
+}
+
+// 默认识别的用量：opencode 的 "12.3K tokens"、"Cost: $0.42"，Claude Code 的 "Context left until auto-compact: 12%"，
+// aider 的 "Tokens: 2.1k sent ... $0.12 session"，codex 的 "tokens used: 12,345"、"85% context left"
+fn default_usage_patterns() -> Vec<String> {
+    let count = r"\d[\d,]*(?:\.\d+)?\s?[km]?";
+    vec![
+        format!(r"tokens:\s*(?P<tokens>{}) sent", count),
+        format!(r"tokens used:?\s*(?P<tokens>{})", count),
+        format!(r"(?:tokens|context):\s*(?P<tokens>{})", count),
+        format!(r"(?P<tokens>{}) tokens", count),
+        r"\$(?P<cost>\d[\d,]*(?:\.\d+)?) session".to_string(),
+        r"cost:?\s*\$(?P<cost>\d[\d,]*(?:\.\d+)?)".to_string(),
+        r"\$(?P<cost>\d[\d,]*\.\d+)".to_string(),
+        r"context left[^:\d]*:?\s*(?P<context_left>\d+(?:\.\d+)?)%".to_string(),
+        r"(?P<context_left>\d+(?:\.\d+)?)% context left".to_string(),
+        r"(?P<context>\d+(?:\.\d+)?)%\s*(?:of\s+)?(?:context|ctx)".to_string(),
+        r"tokens\s*\(?(?P<context>\d+(?:\.\d+)?)%".to_string(),
+    ]
+}
+
+fn default_llm_backend() -> String {
+    "none".to_string()
+}
+
+fn default_llm_model() -> String {
+    "llama3.2".to_string()
+}
+
+// 默认只看屏幕末尾 5 个非空行：用量显示在状态栏，对话内容里的数字不算
+fn default_usage_search_lines() -> usize {
+    5
+}
+
+// 默认保留 720 个采样（5 秒间隔约 1 小时）
+fn default_usage_history() -> usize {
+    720
+}
+
+fn default_wrap_up_prompt() -> String {
+    "用量预算已经用完。请保存当前进度：提交已完成的修改、写好工作记录，然后停下来等待指示".to_string()
+}
+
+fn default_interrupt_keys() -> Vec<String> {
+    vec!["Escape".to_string()]
+}
+
+fn default_loop_commands() -> Vec<String> {
+    vec!["你好像在重复同样的操作。先停下来，回顾一下之前的尝试为什么没有成功，换一种思路再继续".to_string()]
+}
+
+// 默认上下文已满提示
+fn default_context_full_patterns() -> Vec<String> {
+    vec![
+        r"context (window|length) (exceeded|is full)".to_string(),
+        r"maximum context length".to_string(),
+        r"context_length_exceeded".to_string(),
+        r"(prompt|input|conversation) is too long".to_string(),
+    ]
+}
+
+// 默认错误提示
+fn default_error_patterns() -> Vec<String> {
+    vec![
+        r"^\W*(error|fatal)\b".to_string(),
+        r"\bapi error\b".to_string(),
+        r"unexpected error".to_string(),
+        r"request failed".to_string(),
+        r"connection (error|refused|reset)".to_string(),
+        r"panicked at".to_string(),
+        r"traceback \(most recent call last\)".to_string(),
+    ]
+}
+
+// 默认任务完成提示
+fn default_finished_patterns() -> Vec<String> {
+    vec![
+        r"all tasks (are )?(complete|completed|done)".to_string(),
+        r"\btask (is )?(complete|completed|finished)\b".to_string(),
+        r"任务(已|全部)?完成".to_string(),
+        r"全部完成".to_string(),
+    ]
+}
+
+// 默认屏蔽的易变内容：时钟（12:34、12:34:56）和耗时（12s、1.5s、3m、250ms）
+fn default_masks() -> Vec<String> {
+    vec![
+        r"\b\d{1,2}:\d{2}(:\d{2})?\b".to_string(),
+        r"\b\d+(\.\d+)?\s?(ms|s|m|h)\b".to_string(),
+    ]
+}
+
+// 默认开启的规范化步骤
+fn default_true() -> bool {
+    true
+}
+
 // 默认长时间无变化判定时间（秒）
 fn default_long_stuck_sec() -> u64 {
     120  // 2分钟
 }
 
-// 默认干预指令列表
-fn default_commands() -> Vec<String> {
-    vec![
-        "继续".to_string(),
-        "继续工作".to_string(),
-        "请继续".to_string(),
-        "/compact".to_string(),
-        "C-c".to_string(),
-        "请继续工作".to_string(),
-    ]
+// 默认开启批量查询
+fn default_batch_poll() -> bool {
+    true
+}
+
+// 默认控制模式输出合并等待时间（毫秒）
+fn default_control_debounce() -> u64 {
+    200
 }
 
 // 默认指令发送延迟（毫秒）
@@ -106,6 +817,90 @@ impl Config {
         Ok(())
     }
     
+    /// 展开所有监控目标，返回 (名称, 该目标的完整配置)
+    /// 每个目标配置中 tmux.pane 为目标 pane，targets 为空，留空的字段已用该目标的配置档补全
+    pub fn resolve_targets(&self) -> Vec<(String, Config)> {
+        let mut base = self.clone();
+        base.targets = Vec::new();
+
+        if self.targets.is_empty() {
+            base.apply_profile();
+            return vec![(self.tmux.pane.clone(), base)];
+        }
+
+        self.targets
+            .iter()
+            .map(|target| {
+                let mut config = base.clone();
+                config.tmux.pane = target.pane.clone();
+                if let Some(backend) = target.backend {
+                    config.tmux.backend = backend;
+                }
+                if let Some(remote) = &target.remote {
+                    config.tmux.remote = Some(remote.clone());
+                }
+                if target.socket_name.is_some() || target.socket_path.is_some() {
+                    config.tmux.socket_name = target.socket_name.clone();
+                    config.tmux.socket_path = target.socket_path.clone();
+                }
+                if let Some(monitoring) = &target.monitoring {
+                    config.monitoring = monitoring.clone();
+                }
+                if let Some(intervention) = &target.intervention {
+                    config.intervention = intervention.clone();
+                }
+                if let Some(respawn) = &target.respawn {
+                    config.respawn = respawn.clone();
+                }
+                if let Some(normalize) = &target.normalize {
+                    config.normalize = normalize.clone();
+                }
+                if let Some(states) = &target.states {
+                    config.states = states.clone();
+                }
+                if let Some(approval) = &target.approval {
+                    config.approval = approval.clone();
+                }
+                if let Some(rate_limit) = &target.rate_limit {
+                    config.rate_limit = rate_limit.clone();
+                }
+                if let Some(loop_detection) = &target.loop_detection {
+                    config.loop_detection = loop_detection.clone();
+                }
+                if let Some(usage) = &target.usage {
+                    config.usage = usage.clone();
+                }
+                if let Some(indicators) = &target.indicators {
+                    config.indicators = indicators.clone();
+                }
+                if let Some(idle_prompts) = &target.idle_prompts {
+                    config.idle_prompts = Some(idle_prompts.clone());
+                }
+                config.profile = target.profile.or(base.profile);
+                config.apply_profile();
+                let name = target.name.clone().unwrap_or_else(|| target.pane.clone());
+                (name, config)
+            })
+            .collect()
+    }
+
+    /// 卡住时在该状态下循环使用的指令，None 表示不干预
+    /// 未在 on_state 中列出时：等待输入、出现错误、上下文已满使用 commands，等待权限确认、被限流、任务已完成不干预
+    pub fn commands_for(&self, state: AgentState) -> Option<&[String]> {
+        let commands = match self.intervention.on_state.get(&state) {
+            Some(commands) => commands.as_slice(),
+            None => match state {
+                AgentState::IdleAtPrompt | AgentState::ErrorShown | AgentState::ContextFull => &self.intervention.commands,
+                _ => return None,
+            },
+        };
+        if commands.is_empty() {
+            None
+        } else {
+            Some(commands)
+        }
+    }
+
     // 获取下一个干预指令（循环使用）
     pub fn get_next_intervention_command(&self, current_index: usize) -> (String, usize) {
         if self.intervention.commands.is_empty() {
@@ -145,4 +940,119 @@ impl Config {
             // 暂时用简单的内存存储
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_target_from_tmux_pane() {
+        let config = Config::default();
+        let targets = config.resolve_targets();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].0, "%18");
+        assert_eq!(targets[0].1.tmux.pane, "%18");
+    }
+
+    #[test]
+    fn test_commands_for_state() {
+        let mut config = Config::default();
+        config.apply_profile();
+        config.intervention.commands = vec!["继续".to_string()];
+        config.intervention.on_state.insert(AgentState::ErrorShown, Vec::new());
+        assert_eq!(config.commands_for(AgentState::IdleAtPrompt), Some(&["继续".to_string()][..]));
+        assert_eq!(config.commands_for(AgentState::ContextFull), Some(&["/compact".to_string()][..]));
+        // 显式配置为空列表的状态不干预
+        assert_eq!(config.commands_for(AgentState::ErrorShown), None);
+        assert_eq!(config.commands_for(AgentState::AwaitingPermission), None);
+        assert_eq!(config.commands_for(AgentState::Finished), None);
+    }
+
+    #[test]
+    fn test_targets_override_sections() {
+        let yaml = r#"
+tmux:
+  pane: "%18"
+monitoring:
+  interval: 5
+  stuck_sec: 30
+  max_retry: 6
+intervention:
+  commands: ["继续"]
+targets:
+  - pane: "%1"
+  - name: backend
+    pane: "agent:1"
+    backend: screen
+    monitoring:
+      interval: 10
+      stuck_sec: 60
+      max_retry: 2
+    intervention:
+      commands: ["/compact", "continue"]
+  - pane: "%5"
+    remote:
+      host: buildbox
+      user: dev
+  - pane: "%7"
+    socket_name: agents
+    indicators:
+      - pattern: "Thinking"
+      - name: 状态栏
+        pattern: '\d+ tokens'
+        regex: true
+        region:
+          last_lines: 1
+      - pattern: "esc to interrupt"
+        region: status_bar
+  - pane: "%9"
+    profile: gemini-cli
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let targets = config.resolve_targets();
+        assert_eq!(targets.len(), 5);
+
+        let (name, first) = &targets[0];
+        assert_eq!(name, "%1");
+        assert_eq!(first.tmux.pane, "%1");
+        assert_eq!(first.tmux.backend, BackendKind::Tmux);
+        assert_eq!(first.monitoring.interval, 5);
+        assert_eq!(first.intervention.commands, vec!["继续".to_string()]);
+        assert!(first.targets.is_empty());
+
+        let (name, second) = &targets[1];
+        assert_eq!(name, "backend");
+        assert_eq!(second.tmux.backend, BackendKind::Screen);
+        assert_eq!(second.monitoring.interval, 10);
+        assert_eq!(second.monitoring.long_stuck_sec, 120);
+        assert_eq!(second.intervention.commands.len(), 2);
+        assert_eq!(second.intervention.enter_delay_ms, 3000);
+        assert!(second.tmux.remote.is_none());
+
+        let remote = targets[2].1.tmux.remote.as_ref().unwrap();
+        assert_eq!(remote.host, "buildbox");
+        assert_eq!(remote.user.as_deref(), Some("dev"));
+        assert_eq!(remote.control_persist_sec, 600);
+
+        let fourth = &targets[3].1;
+        assert_eq!(fourth.tmux.socket_name.as_deref(), Some("agents"));
+        assert!(fourth.tmux.socket_path.is_none());
+        assert!(first.tmux.socket_name.is_none());
+
+        assert_eq!(first.indicators, ProfileKind::Opencode.profile().indicators);
+        assert_eq!(fourth.indicators.len(), 3);
+        assert_eq!(fourth.indicators[0], IndicatorRule::literal("Thinking"));
+        assert!(fourth.indicators[1].regex);
+        assert_eq!(fourth.indicators[1].region, Some(RegionRef::Inline(ScreenRegion { last_lines: Some(1), ..ScreenRegion::default() })));
+        assert_eq!(fourth.indicators[2].region, Some(RegionRef::Named("status_bar".to_string())));
+
+        // 目标选择的配置档只补全留空的字段，全局显式配置的指令仍然生效
+        let fifth = &targets[4].1;
+        assert_eq!(fifth.profile, Some(ProfileKind::GeminiCli));
+        assert_eq!(fifth.indicators, ProfileKind::GeminiCli.profile().indicators);
+        assert_eq!(fifth.tmux.agent_commands, vec!["gemini".to_string()]);
+        assert_eq!(fifth.intervention.commands, vec!["继续".to_string()]);
+        assert_eq!(first.tmux.agent_commands, vec!["opencode".to_string()]);
+    }
+}
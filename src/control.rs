@@ -0,0 +1,240 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+use crate::tmux::TmuxClient;
+
+/// tmux 控制模式（tmux -C）推送的通知
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlEvent {
+    Output { pane: String, data: Vec<u8> },  // %output / %extended-output
+    Exit,                                    // %exit，控制连接结束
+    Other,                                   // 其它通知（%begin、%layout-change 等）
+}
+
+/// 解析控制模式输出的一行
+pub fn parse_control_line(line: &str) -> ControlEvent {
+    if let Some(rest) = line.strip_prefix("%output ") {
+        if let Some((pane, data)) = rest.split_once(' ') {
+            return ControlEvent::Output { pane: pane.to_string(), data: unescape_output(data) };
+        }
+        return ControlEvent::Output { pane: rest.to_string(), data: Vec::new() };
+    }
+
+    // %extended-output %<pane> <age> ... : <data>
+    if let Some(rest) = line.strip_prefix("%extended-output ") {
+        let pane = rest.split(' ').next().unwrap_or_default().to_string();
+        let data = rest.split_once(" : ").map(|(_, data)| unescape_output(data)).unwrap_or_default();
+        return ControlEvent::Output { pane, data };
+    }
+
+    if line == "%exit" || line.starts_with("%exit ") {
+        return ControlEvent::Exit;
+    }
+
+    ControlEvent::Other
+}
+
+/// 还原 %output 中的八进制转义（\ooo）和反斜杠
+fn unescape_output(data: &str) -> Vec<u8> {
+    let bytes = data.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            let octal = bytes.get(i + 1..i + 4).filter(|digits| digits.iter().all(|b| (b'0'..=b'7').contains(b)));
+            if let Some(digits) = octal {
+                let value = digits.iter().fold(0u32, |acc, b| acc * 8 + u32::from(b - b'0'));
+                result.push(value as u8);
+                i += 4;
+                continue;
+            }
+            if bytes.get(i + 1) == Some(&b'\\') {
+                result.push(b'\\');
+                i += 2;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+
+    result
+}
+
+/// 被监控 pane 的输出信号，由控制模式读取线程更新
+#[derive(Default)]
+pub struct OutputSignal {
+    notify: Notify,
+    total_bytes: AtomicU64,
+    alive: AtomicBool,
+}
+
+impl OutputSignal {
+    /// 创建一个处于连接状态的信号，供其它输出来源（如内置 PTY）使用
+    pub fn connected() -> Self {
+        let signal = OutputSignal::default();
+        signal.alive.store(true, Ordering::SeqCst);
+        signal
+    }
+
+    /// 自连接建立以来收到的输出字节总数
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes.load(Ordering::SeqCst)
+    }
+
+    /// 控制连接是否仍然存活
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    /// 记录一次输出并唤醒等待者
+    pub fn record_output(&self, len: usize) {
+        self.total_bytes.fetch_add(len as u64, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    /// 标记输出来源已关闭
+    pub fn mark_closed(&self) {
+        self.alive.store(false, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    /// 等待下一次输出或超时
+    /// 收到输出后再等待 debounce 时间，把一串连续输出合并成一次检查
+    /// 返回 true 表示由输出唤醒
+    pub async fn wait(&self, timeout: Duration, debounce: Duration) -> bool {
+        if !self.is_alive() {
+            sleep(timeout).await;
+            return false;
+        }
+
+        tokio::select! {
+            _ = self.notify.notified() => {
+                sleep(debounce).await;
+                self.is_alive()
+            }
+            _ = sleep(timeout) => false,
+        }
+    }
+}
+
+/// tmux 控制模式连接
+/// 以只读客户端方式附加到目标 pane 所在的会话，把该 pane 的 %output 转换为 OutputSignal
+pub struct ControlModeWatcher {
+    child: Child,
+    signal: Arc<OutputSignal>,
+}
+
+impl ControlModeWatcher {
+    /// 启动控制模式连接，pane_id 必须是 %N 形式的 pane 标识
+    /// 远程 tmux 的控制连接同样经由 SSH 建立
+    pub fn spawn(tmux: &TmuxClient, pane_id: &str) -> Result<Self> {
+        let mut child = tmux
+            .command(&["-C", "attach-session", "-r", "-t", pane_id])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("无法读取 tmux 控制模式输出"))?;
+
+        let signal = Arc::new(OutputSignal::connected());
+
+        let reader_signal = signal.clone();
+        let watched = pane_id.to_string();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            let mut buf = Vec::new();
+            loop {
+                buf.clear();
+                match reader.read_until(b'\n', &mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+                let line = String::from_utf8_lossy(&buf);
+                match parse_control_line(line.trim_end_matches(['\r', '\n'])) {
+                    ControlEvent::Output { pane, data } if pane == watched => {
+                        reader_signal.record_output(data.len());
+                    }
+                    ControlEvent::Exit => break,
+                    _ => {}
+                }
+            }
+            reader_signal.mark_closed();
+        });
+
+        Ok(ControlModeWatcher { child, signal })
+    }
+
+    /// 获取输出信号
+    pub fn signal(&self) -> Arc<OutputSignal> {
+        self.signal.clone()
+    }
+}
+
+impl Drop for ControlModeWatcher {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_output() {
+        let event = parse_control_line("%output %18 hello\\015\\012world");
+        assert_eq!(
+            event,
+            ControlEvent::Output { pane: "%18".to_string(), data: b"hello\r\nworld".to_vec() }
+        );
+    }
+
+    #[test]
+    fn test_parse_backslash_and_utf8() {
+        let event = parse_control_line("%output %3 a\\\\b 继续");
+        assert_eq!(
+            event,
+            ControlEvent::Output { pane: "%3".to_string(), data: "a\\b 继续".as_bytes().to_vec() }
+        );
+    }
+
+    #[test]
+    fn test_parse_extended_output() {
+        let event = parse_control_line("%extended-output %7 120 : \\033[1mworking");
+        assert_eq!(
+            event,
+            ControlEvent::Output { pane: "%7".to_string(), data: b"\x1b[1mworking".to_vec() }
+        );
+    }
+
+    #[test]
+    fn test_parse_other_events() {
+        assert_eq!(parse_control_line("%exit"), ControlEvent::Exit);
+        assert_eq!(parse_control_line("%exit detached"), ControlEvent::Exit);
+        assert_eq!(parse_control_line("%begin 1 2 0"), ControlEvent::Other);
+        assert_eq!(parse_control_line("%window-add @3"), ControlEvent::Other);
+    }
+
+    #[tokio::test]
+    async fn test_signal_wakes_on_output() {
+        let signal = OutputSignal::connected();
+        signal.record_output(5);
+
+        assert!(signal.wait(Duration::from_secs(5), Duration::from_millis(1)).await);
+        assert_eq!(signal.total_bytes(), 5);
+        assert!(!signal.wait(Duration::from_millis(10), Duration::from_millis(1)).await);
+    }
+}
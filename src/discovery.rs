@@ -0,0 +1,127 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use crate::backend::PaneMetadata;
+
+/// list-panes -a 发现的一个 pane
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredPane {
+    pub target: String,          // session:window.pane 形式的位置
+    pub metadata: PaneMetadata,
+}
+
+/// 判断 pane 是否运行着配置的 agent 程序
+/// 前台命令名（去掉路径）与 agent 名相同，或 pane 标题中包含 agent 名，均视为匹配（忽略大小写）
+pub fn is_agent_pane(pane: &DiscoveredPane, agent_commands: &[String]) -> bool {
+    let command = Path::new(&pane.metadata.current_command)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let title = pane.metadata.title.to_lowercase();
+
+    agent_commands.iter().any(|agent| {
+        let agent = agent.to_lowercase();
+        !agent.is_empty() && (command == agent || title.contains(&agent))
+    })
+}
+
+/// 从全部 pane 中筛选出运行 agent 的 pane
+pub fn find_agent_panes(panes: Vec<DiscoveredPane>, agent_commands: &[String]) -> Vec<DiscoveredPane> {
+    panes.into_iter().filter(|pane| is_agent_pane(pane, agent_commands)).collect()
+}
+
+/// 在发现的 pane 中选择要监控的 pane
+/// 处于交互终端且发现多个时让用户选择（逗号分隔的编号），否则全部监控
+pub fn choose_panes(panes: &[DiscoveredPane]) -> Vec<&DiscoveredPane> {
+    if panes.len() <= 1 || !io::stdin().is_terminal() {
+        return panes.iter().collect();
+    }
+
+    println!("🔍 发现 {} 个 agent pane:", panes.len());
+    for (i, pane) in panes.iter().enumerate() {
+        println!("  [{}] {} {} ({}) {}", i + 1, pane.metadata.pane_id, pane.target, pane.metadata.current_command, pane.metadata.current_path);
+    }
+    print!("请选择要监控的 pane [编号，逗号分隔，默认全部]: ");
+    io::stdout().flush().ok();
+
+    let mut input = String::new();
+    if io::stdin().lock().read_line(&mut input).is_err() {
+        return panes.iter().collect();
+    }
+    parse_selection(&input, panes.len())
+        .into_iter()
+        .map(|index| &panes[index])
+        .collect()
+}
+
+/// 解析用户输入的编号列表（从 1 开始），返回去重后的下标；无有效编号时选择全部
+fn parse_selection(input: &str, count: usize) -> Vec<usize> {
+    let mut selected = Vec::new();
+    for part in input.split([',', ' ']) {
+        if let Ok(number) = part.trim().parse::<usize>() {
+            if (1..=count).contains(&number) && !selected.contains(&(number - 1)) {
+                selected.push(number - 1);
+            }
+        }
+    }
+
+    if selected.is_empty() {
+        (0..count).collect()
+    } else {
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pane(command: &str, title: &str) -> DiscoveredPane {
+        DiscoveredPane {
+            target: "main:0.0".to_string(),
+            metadata: PaneMetadata {
+                pane_id: "%1".to_string(),
+                current_command: command.to_string(),
+                title: title.to_string(),
+                ..PaneMetadata::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_match_by_command() {
+        let agents = vec!["opencode".to_string()];
+        assert!(is_agent_pane(&pane("opencode", "host"), &agents));
+        assert!(is_agent_pane(&pane("/usr/local/bin/OpenCode", ""), &agents));
+        assert!(!is_agent_pane(&pane("bash", "host"), &agents));
+        assert!(!is_agent_pane(&pane("opencode-watch2", "host"), &agents));
+    }
+
+    #[test]
+    fn test_match_by_title() {
+        let agents = vec!["opencode".to_string()];
+        assert!(is_agent_pane(&pane("node", "OpenCode - my-project"), &agents));
+    }
+
+    #[test]
+    fn test_find_agent_panes() {
+        let agents = vec!["opencode".to_string(), "aider".to_string()];
+        let panes = vec![pane("bash", ""), pane("aider", ""), pane("vim", ""), pane("opencode", "")];
+        let found = find_agent_panes(panes, &agents);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].metadata.current_command, "aider");
+        assert_eq!(found[1].metadata.current_command, "opencode");
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("2", 3), vec![1]);
+        assert_eq!(parse_selection("3, 1,3", 3), vec![2, 0]);
+        assert_eq!(parse_selection("", 3), vec![0, 1, 2]);
+        assert_eq!(parse_selection("9", 2), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_empty_agent_name_never_matches() {
+        assert!(!is_agent_pane(&pane("bash", "title"), &[String::new()]));
+    }
+}
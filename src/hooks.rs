@@ -0,0 +1,353 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use anyhow::Result;
+use tokio::sync::Notify;
+
+/// 安装到 tmux 的 hook 在数组中的下标，使用固定下标以免覆盖用户自己的 hook
+const HOOK_INDEX: u32 = 77;
+
+/// tmux hook 推送的事件
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookEvent {
+    Silence,   // alert-silence：窗口在 monitor-silence 秒内没有输出
+    Activity,  // alert-activity：窗口有新输出
+    Bell,      // alert-bell：程序发出响铃
+    Died,      // pane-died：pane 中的程序已退出
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::Silence => "silence",
+            HookEvent::Activity => "activity",
+            HookEvent::Bell => "bell",
+            HookEvent::Died => "died",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "silence" => Some(HookEvent::Silence),
+            "activity" => Some(HookEvent::Activity),
+            "bell" => Some(HookEvent::Bell),
+            "died" => Some(HookEvent::Died),
+            _ => None,
+        }
+    }
+}
+
+/// 解析一条通知：`<事件> <pane 或窗口标识>`
+pub fn parse_notification(line: &str) -> Option<(HookEvent, String)> {
+    let (event, target) = line.trim().split_once(' ')?;
+    Some((HookEvent::from_name(event)?, target.trim().to_string()))
+}
+
+/// 单个目标收到的 hook 事件队列
+#[derive(Default)]
+pub struct HookEvents {
+    queue: Mutex<VecDeque<HookEvent>>,
+    notify: Notify,
+}
+
+impl HookEvents {
+    pub fn push(&self, event: HookEvent) {
+        self.queue.lock().unwrap().push_back(event);
+        self.notify.notify_one();
+    }
+
+    /// 等待下一个事件
+    pub async fn next(&self) -> HookEvent {
+        loop {
+            if let Some(event) = self.queue.lock().unwrap().pop_front() {
+                return event;
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+/// 按 pane 标识（%N）或窗口标识（@N）分发事件
+#[derive(Default)]
+struct Registry {
+    targets: Mutex<HashMap<String, Vec<Arc<HookEvents>>>>,
+}
+
+impl Registry {
+    fn register(&self, key: &str, events: Arc<HookEvents>) {
+        self.targets.lock().unwrap().entry(key.to_string()).or_default().push(events);
+    }
+
+    fn dispatch(&self, line: &str) {
+        let Some((event, target)) = parse_notification(line) else {
+            return;
+        };
+        if let Some(receivers) = self.targets.lock().unwrap().get(&target) {
+            for events in receivers {
+                events.push(event);
+            }
+        }
+    }
+}
+
+/// 把字符串放进双引号，供 run-shell 交给 shell 执行
+fn shell_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in value.chars() {
+        if matches!(ch, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(ch);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// 生成 hook 执行的 tmux 命令：通过 notify 子命令把事件写入本进程的 socket
+/// target_format 是 tmux 格式（#{pane_id} 或 #{window_id}），由 run-shell 展开
+fn hook_command(exe: &str, socket: &str, event: HookEvent, target_format: &str) -> Result<String> {
+    let shell = format!("{} notify --socket {} {} {}", shell_quote(exe), shell_quote(socket), event.name(), target_format);
+    // tmux 单引号字符串中不能再出现单引号
+    if shell.contains('\'') {
+        return Err(anyhow::anyhow!("路径中包含单引号，无法安装 tmux hook: {}", shell));
+    }
+    Ok(format!("run-shell -b '{}'", shell))
+}
+
+#[cfg(unix)]
+pub use listener::{send_notification, HookSet};
+
+#[cfg(unix)]
+mod listener {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use crate::config::Config;
+    use crate::tmux::TmuxClient;
+
+    /// 在本地 socket 上接收 tmux hook 通知
+    struct HookListener {
+        path: PathBuf,
+        registry: Arc<Registry>,
+    }
+
+    impl HookListener {
+        /// 在临时目录创建 socket 并开始接收通知
+        fn bind() -> Result<Self> {
+            let path = std::env::temp_dir().join(format!("opencode-watch-{}.sock", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let listener = UnixListener::bind(&path)?;
+            let registry = Arc::new(Registry::default());
+
+            let accept_registry = registry.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    for line in BufReader::new(stream).lines() {
+                        match line {
+                            Ok(line) => accept_registry.dispatch(&line),
+                            Err(_) => break,
+                        }
+                    }
+                }
+            });
+
+            Ok(HookListener { path, registry })
+        }
+
+        /// 在 pane 及其所在的窗口、会话上安装 hook 和监控选项，返回事件队列和负责清理的守卫
+        /// monitor-silence 使用目标的 stuck_sec，窗口静默这么久后 tmux 主动推送 silence 事件
+        fn install(&self, pane_id: &str, config: &Config, client: TmuxClient) -> Result<(Arc<HookEvents>, HookGuard)> {
+            let window_id = tmux_output(&client, &["display-message", "-p", "-t", pane_id, "#{window_id}"])?;
+            let exe = std::env::current_exe()?.to_string_lossy().to_string();
+            let socket = self.path.to_string_lossy().to_string();
+
+            let events = Arc::new(HookEvents::default());
+            self.registry.register(pane_id, events.clone());
+            self.registry.register(&window_id, events.clone());
+
+            // 记下用户原来的选项值，清理时恢复；先创建守卫，安装中途失败时也会清理已安装的部分
+            let saved = OPTIONS
+                .iter()
+                .map(|&(scope, option)| {
+                    let args: Vec<&str> = ["show-options", "-qv", scope, "-t", pane_id, option].into_iter().filter(|arg| !arg.is_empty()).collect();
+                    let value = tmux_output(&client, &args)?;
+                    Ok((scope, option, (!value.is_empty()).then_some(value)))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let guard = HookGuard { pane_id: pane_id.to_string(), client, saved };
+            let run = |args: &[&str]| tmux(&guard.client, args);
+            run(&["set-option", "-w", "-t", pane_id, "monitor-silence", &config.monitoring.stuck_sec.to_string()])?;
+            run(&["set-option", "-w", "-t", pane_id, "monitor-activity", "on"])?;
+            run(&["set-option", "-w", "-t", pane_id, "monitor-bell", "on"])?;
+            // silence-action 默认只对非当前窗口生效，改为全部窗口；提示改为状态栏消息而不是响铃
+            run(&["set-option", "-t", pane_id, "silence-action", "any"])?;
+            run(&["set-option", "-t", pane_id, "visual-silence", "on"])?;
+            run(&["set-option", "-p", "-t", pane_id, "remain-on-exit", "on"])?;
+            for (hook, event) in WINDOW_HOOKS {
+                let command = hook_command(&exe, &socket, event, "#{window_id}")?;
+                run(&["set-hook", "-t", pane_id, &format!("{}[{}]", hook, HOOK_INDEX), &command])?;
+            }
+            for (hook, event) in PANE_HOOKS {
+                let command = hook_command(&exe, &socket, event, "#{hook_pane}")?;
+                run(&["set-hook", "-p", "-t", pane_id, &format!("{}[{}]", hook, HOOK_INDEX), &command])?;
+            }
+
+            Ok((events, guard))
+        }
+    }
+
+    impl Drop for HookListener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    /// alert hook：tmux 中属于会话级 hook，事件携带触发的窗口
+    const WINDOW_HOOKS: [(&str, HookEvent); 3] = [
+        ("alert-silence", HookEvent::Silence),
+        ("alert-activity", HookEvent::Activity),
+        ("alert-bell", HookEvent::Bell),
+    ];
+
+    /// 安装时修改的选项及其作用域：-w 窗口、-p pane，空表示会话
+    const OPTIONS: [(&str, &str); 6] = [
+        ("-w", "monitor-silence"),
+        ("-w", "monitor-activity"),
+        ("-w", "monitor-bell"),
+        ("", "silence-action"),
+        ("", "visual-silence"),
+        ("-p", "remain-on-exit"),
+    ];
+
+    /// pane 级 hook
+    /// pane-exited 只会查找全局 hook，因此在 pane 上开启 remain-on-exit，改用 pane-died
+    const PANE_HOOKS: [(&str, HookEvent); 1] = [("pane-died", HookEvent::Died)];
+
+    /// 所有目标共用一个监听器，首次安装时创建；析构时移除全部已安装的 hook
+    #[derive(Default)]
+    pub struct HookSet {
+        listener: Option<HookListener>,
+        guards: Vec<HookGuard>,
+    }
+
+    impl HookSet {
+        /// client 决定 hook 安装到哪个 tmux 服务器
+        pub fn install(&mut self, pane_id: &str, config: &Config, client: TmuxClient) -> Result<Arc<HookEvents>> {
+            let listener = match &mut self.listener {
+                Some(listener) => listener,
+                None => self.listener.insert(HookListener::bind()?),
+            };
+            let (events, guard) = listener.install(pane_id, config, client)?;
+            self.guards.push(guard);
+            Ok(events)
+        }
+    }
+
+    /// 析构时移除安装的 hook 并恢复监控选项
+    struct HookGuard {
+        pane_id: String,
+        client: TmuxClient,
+        saved: Vec<(&'static str, &'static str, Option<String>)>,  // (作用域, 选项, 安装前在该作用域上设置的值)，None 表示原来未设置
+    }
+
+    impl Drop for HookGuard {
+        fn drop(&mut self) {
+            let pane = self.pane_id.as_str();
+            let run = |args: &[&str]| tmux(&self.client, args);
+            for (hook, _) in WINDOW_HOOKS {
+                let _ = run(&["set-hook", "-u", "-t", pane, &format!("{}[{}]", hook, HOOK_INDEX)]);
+            }
+            for (hook, _) in PANE_HOOKS {
+                let _ = run(&["set-hook", "-pu", "-t", pane, &format!("{}[{}]", hook, HOOK_INDEX)]);
+            }
+            for (scope, option, value) in &self.saved {
+                let _ = run(&restore_option_args(scope, option, value.as_deref(), pane));
+            }
+        }
+    }
+
+    /// 恢复选项的 tmux 参数：原来设置过的写回原值，原来未设置的取消设置，继续继承上一级
+    pub(super) fn restore_option_args<'a>(scope: &'a str, option: &'a str, value: Option<&'a str>, pane: &'a str) -> Vec<&'a str> {
+        let args = match value {
+            Some(value) => vec!["set-option", scope, "-t", pane, option, value],
+            None => vec!["set-option", scope, "-u", "-t", pane, option],
+        };
+        args.into_iter().filter(|arg| !arg.is_empty()).collect()
+    }
+
+    /// notify 子命令：由 tmux hook 调用，把事件写入监控进程的 socket
+    pub fn send_notification(socket: &Path, event: &str, target: &str) -> Result<()> {
+        let mut stream = UnixStream::connect(socket)?;
+        writeln!(stream, "{} {}", event, target)?;
+        Ok(())
+    }
+
+    fn tmux(client: &TmuxClient, args: &[&str]) -> Result<()> {
+        tmux_output(client, args).map(|_| ())
+    }
+
+    /// 同步执行：HookGuard 在析构时也要调用，不能依赖异步运行时
+    fn tmux_output(client: &TmuxClient, args: &[&str]) -> Result<String> {
+        let output = client.command(args).output()?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("tmux {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr)));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_notification() {
+        assert_eq!(parse_notification("silence @3\n"), Some((HookEvent::Silence, "@3".to_string())));
+        assert_eq!(parse_notification("died %18"), Some((HookEvent::Died, "%18".to_string())));
+        assert_eq!(parse_notification("unknown %18"), None);
+        assert_eq!(parse_notification("died"), None);
+    }
+
+    #[test]
+    fn test_hook_command_quoting() {
+        let command = hook_command("/opt/my tools/opencode-watch", "/tmp/ow.sock", HookEvent::Died, "#{hook_pane}").unwrap();
+        assert_eq!(
+            command,
+            "run-shell -b '\"/opt/my tools/opencode-watch\" notify --socket \"/tmp/ow.sock\" died #{hook_pane}'"
+        );
+        assert!(hook_command("/tmp/it's", "/tmp/ow.sock", HookEvent::Bell, "#{window_id}").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_option_args() {
+        use listener::restore_option_args;
+        assert_eq!(
+            restore_option_args("-w", "monitor-activity", Some("on"), "%18"),
+            ["set-option", "-w", "-t", "%18", "monitor-activity", "on"]
+        );
+        assert_eq!(restore_option_args("-p", "remain-on-exit", None, "%18"), ["set-option", "-p", "-u", "-t", "%18", "remain-on-exit"]);
+        // 会话选项不带作用域参数
+        assert_eq!(restore_option_args("", "visual-silence", None, "%18"), ["set-option", "-u", "-t", "%18", "visual-silence"]);
+    }
+
+    #[tokio::test]
+    async fn test_registry_dispatches_by_pane_and_window() {
+        let registry = Registry::default();
+        let events = Arc::new(HookEvents::default());
+        registry.register("%18", events.clone());
+        registry.register("@3", events.clone());
+
+        registry.dispatch("silence @3");
+        registry.dispatch("bell @4");
+        registry.dispatch("died %18");
+
+        assert_eq!(events.next().await, HookEvent::Silence);
+        assert_eq!(events.next().await, HookEvent::Died);
+        assert!(events.queue.lock().unwrap().is_empty());
+    }
+}
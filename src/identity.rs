@@ -0,0 +1,139 @@
+use std::fs;
+use anyhow::Result;
+use crate::discovery::DiscoveredPane;
+
+/// 配置中的 pane 目标
+/// `%18` 按 pane 标识匹配，`title:名称` 按 pane 标题匹配，其余按 `session:window.pane` 位置匹配，均为完全相等
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaneTarget {
+    Id(String),        // %N 形式的 pane 标识
+    Position(String),  // session:window.pane
+    Title(String),     // pane 标题
+}
+
+impl PaneTarget {
+    pub fn parse(target: &str) -> Self {
+        if let Some(title) = target.strip_prefix("title:") {
+            PaneTarget::Title(title.to_string())
+        } else if target.starts_with('%') {
+            PaneTarget::Id(target.to_string())
+        } else {
+            PaneTarget::Position(target.to_string())
+        }
+    }
+
+    pub fn matches(&self, pane: &DiscoveredPane) -> bool {
+        match self {
+            PaneTarget::Id(id) => pane.metadata.pane_id == *id,
+            PaneTarget::Position(position) => pane.target == *position,
+            PaneTarget::Title(title) => pane.metadata.title == *title,
+        }
+    }
+}
+
+/// 在 pane 列表中查找目标，没有找到时返回 None
+/// 按标题匹配到多个 pane 时无法确定目标，返回错误
+pub fn find_pane<'a>(panes: &'a [DiscoveredPane], target: &str) -> Result<Option<&'a DiscoveredPane>> {
+    let target = PaneTarget::parse(target);
+    let mut matched = panes.iter().filter(|pane| target.matches(pane));
+
+    let first = matched.next();
+    if let (Some(first), Some(second)) = (first, matched.next()) {
+        return Err(anyhow::anyhow!(
+            "目标 {:?} 匹配到多个 pane（{}、{} 等），请改用 pane 标识",
+            target,
+            first.metadata.pane_id,
+            second.metadata.pane_id
+        ));
+    }
+
+    Ok(first)
+}
+
+/// pane 主进程指纹：pid 加进程启动时间
+/// pane 标识被回收复用后，新 pane 的主进程指纹必然不同
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaneFingerprint {
+    pub pid: u32,
+    pub start_time: Option<u64>,  // 进程启动时间（自系统启动以来的时钟滴答数），无法读取时为 None
+}
+
+impl PaneFingerprint {
+    pub fn of_pid(pid: u32) -> Self {
+        PaneFingerprint { pid, start_time: process_start_time(pid) }
+    }
+}
+
+/// 读取进程启动时间（/proc/<pid>/stat 第 22 个字段），非 Linux 或进程不存在时返回 None
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    parse_stat_start_time(&stat)
+}
+
+/// 解析 /proc/<pid>/stat 中的启动时间
+/// 进程名（第 2 个字段）放在括号中且可能包含空格，从最后一个右括号之后开始计数
+pub fn parse_stat_start_time(stat: &str) -> Option<u64> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    // 右括号之后从第 3 个字段（state）开始，启动时间是第 22 个字段
+    rest.split_whitespace().nth(19)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::PaneMetadata;
+
+    fn pane(target: &str, pane_id: &str, title: &str) -> DiscoveredPane {
+        DiscoveredPane {
+            target: target.to_string(),
+            metadata: PaneMetadata {
+                pane_id: pane_id.to_string(),
+                title: title.to_string(),
+                ..PaneMetadata::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(PaneTarget::parse("%18"), PaneTarget::Id("%18".to_string()));
+        assert_eq!(PaneTarget::parse("work:1.0"), PaneTarget::Position("work:1.0".to_string()));
+        assert_eq!(PaneTarget::parse("title:前端 agent"), PaneTarget::Title("前端 agent".to_string()));
+    }
+
+    #[test]
+    fn test_pane_id_is_matched_exactly() {
+        let panes = vec![pane("work:0.0", "%18", "a"), pane("work:0.1", "%1", "b")];
+        assert_eq!(find_pane(&panes, "%1").unwrap().unwrap().target, "work:0.1");
+        assert_eq!(find_pane(&panes, "%18").unwrap().unwrap().target, "work:0.0");
+        assert!(find_pane(&panes, "%8").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_position_and_title() {
+        let panes = vec![pane("work:0.0", "%3", "opencode"), pane("work:10.0", "%4", "shell")];
+        assert_eq!(find_pane(&panes, "work:10.0").unwrap().unwrap().metadata.pane_id, "%4");
+        assert!(find_pane(&panes, "work:1.0").unwrap().is_none());
+        assert_eq!(find_pane(&panes, "title:opencode").unwrap().unwrap().metadata.pane_id, "%3");
+        assert!(find_pane(&panes, "title:open").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ambiguous_title() {
+        let panes = vec![pane("a:0.0", "%1", "opencode"), pane("b:0.0", "%2", "opencode")];
+        assert!(find_pane(&panes, "title:opencode").is_err());
+    }
+
+    #[test]
+    fn test_parse_stat_start_time() {
+        let stat = "4242 (my (odd) cmd) S 1 4242 4242 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 987654 1000 10";
+        assert_eq!(parse_stat_start_time(stat), Some(987654));
+        assert_eq!(parse_stat_start_time("garbage"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_own_process_start_time() {
+        assert!(process_start_time(std::process::id()).is_some());
+    }
+}
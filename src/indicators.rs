@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use regex::{Regex, RegexBuilder};
+use crate::config::{IndicatorRule, RegionRef, ScreenRegion};
+
+/// 编译后的一条活动标识规则
+struct Indicator {
+    name: String,
+    regex: Regex,
+    negative: bool,
+    region: Option<Region>,
+}
+
+/// 一次匹配的结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndicatorMatch {
+    pub positive: Vec<String>,     // 匹配的正向规则
+    pub negative: Option<String>,  // 匹配的第一条反向规则
+}
+
+impl IndicatorMatch {
+    /// 正向规则匹配且没有反向规则匹配
+    pub fn is_active(&self) -> bool {
+        !self.positive.is_empty() && self.negative.is_none()
+    }
+}
+
+/// 内置的命名区域，regions 中同名的定义优先
+/// status_bar：屏幕最下面两行；input_box：最后一个 ╭/┌ 开头的行到其后第一个 ╰/└ 开头的行
+pub fn builtin_region(name: &str) -> Option<ScreenRegion> {
+    match name {
+        "status_bar" => Some(ScreenRegion { last_lines: Some(2), ..ScreenRegion::default() }),
+        "input_box" => Some(ScreenRegion {
+            start_anchor: Some(r"^\s*[╭┌]".to_string()),
+            end_anchor: Some(r"^\s*[╰└]".to_string()),
+            ..ScreenRegion::default()
+        }),
+        _ => None,
+    }
+}
+
+/// 编译后的屏幕区域
+pub struct Region {
+    spec: ScreenRegion,
+    start_anchor: Option<Regex>,
+    end_anchor: Option<Regex>,
+}
+
+impl Region {
+    pub fn compile(spec: &ScreenRegion) -> Result<Self, regex::Error> {
+        let compile = |pattern: &Option<String>| pattern.as_deref().map(Regex::new).transpose();
+        Ok(Region {
+            start_anchor: compile(&spec.start_anchor)?,
+            end_anchor: compile(&spec.end_anchor)?,
+            spec: spec.clone(),
+        })
+    }
+
+    /// 截取区域内的文本，末尾的空行不计入行数
+    pub fn text(&self, content: &str) -> String {
+        let mut lines: Vec<&str> = content.lines().collect();
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+
+        if let Some(anchor) = &self.start_anchor {
+            match lines.iter().rposition(|line| anchor.is_match(line)) {
+                Some(start) => {
+                    lines.drain(..start);
+                }
+                None => return String::new(),
+            }
+        }
+        if let Some(anchor) = &self.end_anchor {
+            // 起始行本身不作为结束锚点，例如 ╭ 和 ╰ 用同一个正则时
+            let skip = usize::from(self.start_anchor.is_some());
+            if let Some(end) = lines.iter().skip(skip).position(|line| anchor.is_match(line)) {
+                lines.truncate(end + skip + 1);
+            }
+        }
+
+        if self.spec.start_row.is_some() || self.spec.end_row.is_some() {
+            let len = lines.len() as isize;
+            let index = |row: isize| if row < 0 { len + row } else { row };
+            let start = index(self.spec.start_row.unwrap_or(0)).max(0);
+            let end = index(self.spec.end_row.unwrap_or(-1)).min(len - 1);
+            if start > end {
+                return String::new();
+            }
+            lines = lines[start as usize..=end as usize].to_vec();
+        }
+
+        if let Some(n) = self.spec.first_lines {
+            lines.truncate(n);
+        }
+        if let Some(n) = self.spec.last_lines {
+            lines.drain(..lines.len().saturating_sub(n));
+        }
+        lines.join("\n")
+    }
+}
+
+/// 配置中的全部活动标识
+/// 字面规则也编译为正则，匹配时不再区分
+pub struct IndicatorSet {
+    indicators: Vec<Indicator>,
+}
+
+impl IndicatorSet {
+    /// 无效的正则或未定义的区域会让该规则被忽略并给出警告
+    pub fn new(rules: &[IndicatorRule], regions: &HashMap<String, ScreenRegion>) -> Self {
+        let indicators = rules
+            .iter()
+            .filter_map(|rule| {
+                let pattern = if rule.regex { rule.pattern.clone() } else { regex::escape(&rule.pattern) };
+                let regex = match RegexBuilder::new(&pattern).case_insensitive(rule.ignore_case).build() {
+                    Ok(regex) => regex,
+                    Err(e) => {
+                        eprintln!("⚠️ 忽略无效的活动标识正则 {:?}: {}", rule.pattern, e);
+                        return None;
+                    }
+                };
+                let region = match &rule.region {
+                    Some(region) => Some(resolve_region(region, regions, &rule.pattern)?),
+                    None => None,
+                };
+                Some(Indicator {
+                    name: rule.name.clone().unwrap_or_else(|| rule.pattern.clone()),
+                    regex,
+                    negative: rule.negative,
+                    region,
+                })
+            })
+            .collect();
+        IndicatorSet { indicators }
+    }
+
+    /// 在屏幕内容中匹配全部规则
+    pub fn check(&self, content: &str) -> IndicatorMatch {
+        let mut result = IndicatorMatch::default();
+        for indicator in &self.indicators {
+            let text = match &indicator.region {
+                Some(region) => region.text(content),
+                None => content.to_string(),
+            };
+            if !indicator.regex.is_match(&text) {
+                continue;
+            }
+            if indicator.negative {
+                result.negative.get_or_insert_with(|| indicator.name.clone());
+            } else {
+                result.positive.push(indicator.name.clone());
+            }
+        }
+        result
+    }
+}
+
+/// 查找命名区域并编译，失败时给出警告
+fn resolve_region(region: &RegionRef, regions: &HashMap<String, ScreenRegion>, pattern: &str) -> Option<Region> {
+    let spec = match region {
+        RegionRef::Inline(spec) => spec.clone(),
+        RegionRef::Named(name) => match regions.get(name).cloned().or_else(|| builtin_region(name)) {
+            Some(spec) => spec,
+            None => {
+                eprintln!("⚠️ 忽略活动标识 {:?}：未定义的屏幕区域 {}", pattern, name);
+                return None;
+            }
+        },
+    };
+    Region::compile(&spec)
+        .map_err(|e| eprintln!("⚠️ 忽略活动标识 {:?}：无效的区域锚点正则: {}", pattern, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str) -> IndicatorRule {
+        IndicatorRule::literal(pattern)
+    }
+
+    fn compile(rules: &[IndicatorRule]) -> IndicatorSet {
+        IndicatorSet::new(rules, &HashMap::new())
+    }
+
+    fn region_text(content: &str, spec: &ScreenRegion) -> String {
+        Region::compile(spec).unwrap().text(content)
+    }
+
+    #[test]
+    fn test_default_indicators() {
+        let set = IndicatorSet::new(&crate::config::ProfileKind::Opencode.profile().indicators, &HashMap::new());
+        assert!(set.check("⠋ working...").is_active());
+        assert_eq!(set.check("press esc interrupt").positive, vec!["esc interrupt".to_string()]);
+        assert!(!set.check("Working").is_active());
+        assert!(!set.check("idle").is_active());
+    }
+
+    #[test]
+    fn test_regex_and_ignore_case() {
+        let set = compile(&[
+            IndicatorRule { regex: true, name: Some("tokens".to_string()), ..rule(r"\d+ tokens") },
+            IndicatorRule { ignore_case: true, ..rule("thinking") },
+        ]);
+        assert_eq!(set.check("1234 tokens").positive, vec!["tokens".to_string()]);
+        assert!(!set.check("many tokens").is_active());
+        assert!(set.check("Thinking…").is_active());
+        // 字面规则中的正则元字符按原样匹配
+        assert!(compile(&[rule("(esc)")]).check("(esc) to interrupt").is_active());
+    }
+
+    #[test]
+    fn test_negative_rule_wins() {
+        let set = compile(&[rule("working"), IndicatorRule { negative: true, ..rule("Allow this action?") }]);
+        let result = set.check("working\nAllow this action? (y/n)");
+        assert_eq!(result.negative, Some("Allow this action?".to_string()));
+        assert!(!result.is_active());
+        // 只有反向规则不匹配时也不算工作
+        assert!(!compile(&[IndicatorRule { negative: true, ..rule("x") }]).check("y").is_active());
+    }
+
+    #[test]
+    fn test_region() {
+        let content = "working on it\nlog line\nstatus: idle\n\n\n";
+        let region = ScreenRegion { last_lines: Some(1), ..ScreenRegion::default() };
+        assert_eq!(region_text(content, &region), "status: idle");
+        assert_eq!(region_text(content, &ScreenRegion { first_lines: Some(2), ..ScreenRegion::default() }), "working on it\nlog line");
+
+        let set = compile(&[IndicatorRule { region: Some(RegionRef::Inline(region)), ..rule("working") }]);
+        assert!(!set.check(content).is_active());
+        assert!(set.check("old output\nworking\n").is_active());
+    }
+
+    #[test]
+    fn test_row_range() {
+        let content = "a\nb\nc\nd\n\n";
+        let rows = |start, end| region_text(content, &ScreenRegion { start_row: start, end_row: end, ..ScreenRegion::default() });
+        assert_eq!(rows(Some(1), Some(2)), "b\nc");
+        assert_eq!(rows(Some(-2), None), "c\nd");
+        assert_eq!(rows(None, Some(-4)), "a");
+        assert_eq!(rows(Some(3), Some(1)), "");
+        assert_eq!(rows(Some(10), None), "");
+    }
+
+    #[test]
+    fn test_anchor_region() {
+        let screen = "╭────╮\n│ working on old task │\n╰────╯\nworking\n╭────╮\n│ > fix the bug │\n╰────╯\n  ? for shortcuts\n";
+        let input_box = region_text(screen, &builtin_region("input_box").unwrap());
+        assert_eq!(input_box, "╭────╮\n│ > fix the bug │\n╰────╯");
+        // 起始锚点不存在时区域为空
+        let missing = ScreenRegion { start_anchor: Some("^NOPE".to_string()), ..ScreenRegion::default() };
+        assert_eq!(region_text(screen, &missing), "");
+        // 没有结束锚点时到末尾
+        let tail = ScreenRegion { start_anchor: Some("^working".to_string()), end_anchor: Some("^NOPE".to_string()), ..ScreenRegion::default() };
+        assert_eq!(region_text(screen, &tail).lines().count(), 5);
+    }
+
+    #[test]
+    fn test_transcript_text_is_not_activity() {
+        let mut regions = HashMap::new();
+        regions.insert("status_bar".to_string(), ScreenRegion { last_lines: Some(1), ..ScreenRegion::default() });
+        let set = IndicatorSet::new(&[IndicatorRule { region: Some(RegionRef::Named("status_bar".to_string())), ..rule("working") }], &regions);
+        // agent 输出中出现 working 不算工作
+        assert!(!set.check("$ cat notes.md\nstill working on parser\n\n> \nidle · 3 files").is_active());
+        assert!(set.check("output\n⠋ working").is_active());
+        // 未定义的区域使该规则被忽略
+        let unknown = IndicatorSet::new(&[IndicatorRule { region: Some(RegionRef::Named("sidebar".to_string())), ..rule("x") }], &regions);
+        assert!(unknown.indicators.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_regex_is_ignored() {
+        let set = compile(&[IndicatorRule { regex: true, ..rule("(") }, rule("ok")]);
+        assert_eq!(set.indicators.len(), 1);
+        let anchor = ScreenRegion { start_anchor: Some("[".to_string()), ..ScreenRegion::default() };
+        assert!(Region::compile(&anchor).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use regex::Regex;
+use lazy_static::lazy_static;
+use crate::backend::PaneBackend;
+
+/// 一条干预指令的发送方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Intervention {
+    Key(String),    // 按键名（例如 C-c、Enter），由后端解释
+    Text(String),   // 原样输入的文本（tmux send-keys -l）
+    Paste(String),  // 多行文本，通过粘贴缓冲区以 bracketed paste 方式送达
+}
+
+lazy_static! {
+    /// 带修饰键的按键名，例如 C-c、M-x、C-M-Up
+    static ref MODIFIED_KEY: Regex = Regex::new(r"^(?:[CMS]-)+(?:\S|[A-Z][A-Za-z0-9]+)$").unwrap();
+}
+
+/// 不带修饰键的 tmux 按键名
+const KEY_NAMES: &[&str] = &[
+    "Enter", "Escape", "Tab", "BTab", "BSpace", "Space", "Up", "Down", "Left", "Right",
+    "Home", "End", "PageUp", "PageDown", "PgUp", "PgDn", "PPage", "NPage", "IC", "DC",
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+];
+
+/// 判断字符串是否是 tmux 按键名
+pub fn is_key_name(value: &str) -> bool {
+    KEY_NAMES.contains(&value) || MODIFIED_KEY.is_match(value)
+}
+
+impl Intervention {
+    /// 解析配置中的干预指令
+    /// 支持显式前缀 `key:`、`text:`、`paste:`；没有前缀时，恰好是按键名的按按键发送，
+    /// 包含换行的按多行粘贴发送，其余按原样文本发送
+    pub fn parse(command: &str) -> Self {
+        if let Some(key) = command.strip_prefix("key:") {
+            return Intervention::Key(key.to_string());
+        }
+        if let Some(text) = command.strip_prefix("text:") {
+            return Intervention::Text(text.to_string());
+        }
+        if let Some(text) = command.strip_prefix("paste:") {
+            return Intervention::Paste(text.to_string());
+        }
+
+        if is_key_name(command) {
+            Intervention::Key(command.to_string())
+        } else if command.contains('\n') {
+            Intervention::Paste(command.trim_end_matches('\n').to_string())
+        } else {
+            Intervention::Text(command.to_string())
+        }
+    }
+
+    /// 发送后是否需要补发回车提交
+    /// 按键不需要；以 / 开头的斜杠命令由 agent 自行补全执行，也不需要
+    pub fn needs_enter(&self) -> bool {
+        match self {
+            Intervention::Key(_) => false,
+            Intervention::Text(text) => !text.starts_with('/'),
+            Intervention::Paste(_) => true,
+        }
+    }
+
+    /// 通过后端发送
+    pub async fn deliver<B: PaneBackend + ?Sized>(&self, backend: &B, pane: &str) -> Result<()> {
+        match self {
+            Intervention::Key(key) => backend.send_keys(pane, key).await,
+            Intervention::Text(text) => backend.send_text(pane, text).await,
+            Intervention::Paste(text) => backend.paste_text(pane, text).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_names() {
+        assert!(is_key_name("C-c"));
+        assert!(is_key_name("Enter"));
+        assert!(is_key_name("M-Up"));
+        assert!(is_key_name("C-M-x"));
+        assert!(!is_key_name("继续"));
+        assert!(!is_key_name("Enter the code"));
+        assert!(!is_key_name("C-"));
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        assert_eq!(Intervention::parse("C-c"), Intervention::Key("C-c".to_string()));
+        assert_eq!(Intervention::parse("继续"), Intervention::Text("继续".to_string()));
+        assert_eq!(Intervention::parse("/compact"), Intervention::Text("/compact".to_string()));
+        assert_eq!(
+            Intervention::parse("第一行\n第二行\n"),
+            Intervention::Paste("第一行\n第二行".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_prefixes() {
+        assert_eq!(Intervention::parse("text:Enter"), Intervention::Text("Enter".to_string()));
+        assert_eq!(Intervention::parse("key:Escape"), Intervention::Key("Escape".to_string()));
+        assert_eq!(Intervention::parse("paste:one line"), Intervention::Paste("one line".to_string()));
+    }
+
+    #[test]
+    fn test_needs_enter() {
+        assert!(!Intervention::parse("C-c").needs_enter());
+        assert!(!Intervention::parse("/compact").needs_enter());
+        assert!(Intervention::parse("继续").needs_enter());
+        assert!(Intervention::parse("a\nb").needs_enter());
+    }
+}
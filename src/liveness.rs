@@ -0,0 +1,154 @@
+use std::path::Path;
+use regex::Regex;
+use crate::backend::PaneMetadata;
+
+/// 常见的交互式 shell，agent 退出后前台进程会回到它们
+const SHELLS: [&str; 9] = ["bash", "zsh", "fish", "sh", "dash", "ksh", "tcsh", "csh", "nu"];
+
+/// agent 已退出的迹象
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentExit {
+    PaneDead,                           // pane 中的程序已退出，remain-on-exit 保留了 pane
+    ReturnedToShell { shell: String },  // 前台进程从 agent 变回了 shell
+    ShellPrompt { line: String },       // 屏幕最后一行是 shell 提示符
+}
+
+impl AgentExit {
+    pub fn describe(&self) -> String {
+        match self {
+            AgentExit::PaneDead => "pane 中的程序已退出 (pane_dead)".to_string(),
+            AgentExit::ReturnedToShell { shell } => format!("前台进程回到了 {}", shell),
+            AgentExit::ShellPrompt { line } => format!("出现 shell 提示符: {}", line),
+        }
+    }
+}
+
+/// 前台命令是否为 shell（忽略路径和登录 shell 的 `-` 前缀）
+pub fn is_shell(command: &str) -> bool {
+    let name = Path::new(command.trim())
+        .file_name()
+        .map(|name| name.to_string_lossy().trim_start_matches('-').to_string())
+        .unwrap_or_default();
+    SHELLS.contains(&name.as_str())
+}
+
+/// 判断 agent 是否已经退出
+/// 记录开始监控时的前台命令，之后依次检查 pane_dead、前台进程是否回到 shell、屏幕上是否出现 shell 提示符
+pub struct ExitDetector {
+    agent_command: Option<String>,  // 开始监控时前台运行的 agent，前台本来就是 shell 时为 None
+    prompts: Vec<Regex>,
+}
+
+impl ExitDetector {
+    /// 无效的提示符正则会被忽略并给出警告
+    pub fn new(shell_prompts: &[String]) -> Self {
+        let prompts = shell_prompts
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    eprintln!("⚠️ 忽略无效的 shell 提示符正则 {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        ExitDetector { agent_command: None, prompts }
+    }
+
+    /// 记录开始监控时的前台命令，shell 不算 agent
+    pub fn record_agent(&mut self, command: &str) {
+        if !command.is_empty() && !is_shell(command) {
+            self.agent_command = Some(command.to_string());
+        }
+    }
+
+    pub fn agent_command(&self) -> Option<&str> {
+        self.agent_command.as_deref()
+    }
+
+    pub fn detect(&self, metadata: &PaneMetadata, content: &str) -> Option<AgentExit> {
+        if metadata.dead {
+            return Some(AgentExit::PaneDead);
+        }
+
+        let at_shell = is_shell(&metadata.current_command);
+        if at_shell && self.agent_command.is_some() {
+            return Some(AgentExit::ReturnedToShell { shell: metadata.current_command.clone() });
+        }
+
+        // 前台命令未知（后端不提供）或本来就是 shell 时，以屏幕上的提示符为准
+        if at_shell || metadata.current_command.is_empty() {
+            let line = content.lines().map(str::trim_end).rfind(|line| !line.is_empty())?;
+            if self.prompts.iter().any(|prompt| prompt.is_match(line)) {
+                return Some(AgentExit::ShellPrompt { line: line.to_string() });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RespawnConfig;
+
+    fn detector() -> ExitDetector {
+        ExitDetector::new(&RespawnConfig::default().shell_prompts)
+    }
+
+    fn metadata(command: &str) -> PaneMetadata {
+        PaneMetadata { current_command: command.to_string(), ..PaneMetadata::default() }
+    }
+
+    #[test]
+    fn test_is_shell() {
+        assert!(is_shell("bash"));
+        assert!(is_shell("-zsh"));
+        assert!(is_shell("/usr/bin/fish"));
+        assert!(!is_shell("opencode"));
+        assert!(!is_shell("node"));
+        assert!(!is_shell(""));
+    }
+
+    #[test]
+    fn test_pane_dead() {
+        let detector = detector();
+        let dead = PaneMetadata { dead: true, ..metadata("opencode") };
+        assert_eq!(detector.detect(&dead, "working"), Some(AgentExit::PaneDead));
+    }
+
+    #[test]
+    fn test_returned_to_shell() {
+        let mut detector = detector();
+        detector.record_agent("opencode");
+        assert_eq!(detector.agent_command(), Some("opencode"));
+        assert_eq!(detector.detect(&metadata("opencode"), "dev@box:~/app$"), None);
+        assert_eq!(
+            detector.detect(&metadata("bash"), "Error: crashed\n"),
+            Some(AgentExit::ReturnedToShell { shell: "bash".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_shell_prompt_when_agent_unknown() {
+        let mut detector = detector();
+        detector.record_agent("zsh");
+        assert_eq!(detector.agent_command(), None);
+
+        let prompt = |line: &str| Some(AgentExit::ShellPrompt { line: line.to_string() });
+        assert_eq!(detector.detect(&metadata("zsh"), "opencode exited\ndev@box:~/app$ \n\n"), prompt("dev@box:~/app$"));
+        assert_eq!(detector.detect(&metadata(""), "[dev@box app]$"), prompt("[dev@box app]$"));
+        assert_eq!(detector.detect(&metadata("bash"), "bash-5.2#"), prompt("bash-5.2#"));
+        // agent 界面中的普通内容不是提示符
+        assert_eq!(detector.detect(&metadata(""), "> 继续工作\ncost: $0.42"), None);
+        // 前台是 agent 时不看提示符
+        assert_eq!(detector.detect(&metadata("claude"), "dev@box:~/app$"), None);
+    }
+
+    #[test]
+    fn test_invalid_prompt_pattern_is_ignored() {
+        let detector = ExitDetector::new(&["[".to_string(), r"^\$$".to_string()]);
+        assert_eq!(detector.prompts.len(), 1);
+        assert_eq!(detector.detect(&metadata("sh"), "$"), Some(AgentExit::ShellPrompt { line: "$".to_string() }));
+    }
+}
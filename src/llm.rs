@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use ollama_rs::generation::completion::request::GenerationRequest;
 use anyhow::Result;
+use crate::config::ProfileKind;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LlmRequest {
@@ -17,6 +18,7 @@ pub struct LlmResponse {
 pub struct LlmClient {
     backend: String,
     model: String,
+    prompt: String,  // 放在 pane 内容前的提示语，随 agent 配置档变化
 }
 
 impl LlmClient {
@@ -24,8 +26,20 @@ impl LlmClient {
         LlmClient {
             backend: backend.to_string(),
             model: model.to_string(),
+            prompt: ProfileKind::Opencode.profile().llm_prompt,
         }
     }
+
+    /// 使用配置档中针对该 agent 的提示语
+    pub fn with_prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+
+    /// 发送给 LLM 的完整提示：配置档的提示语加 pane 内容
+    pub fn prompt_for(&self, content: &str) -> String {
+        format!("{}\n\n{}", self.prompt, content)
+    }
     
     pub async fn analyze_state(&self, content: &str) -> Result<String> {
         match self.backend.as_str() {
@@ -40,10 +54,7 @@ impl LlmClient {
     async fn analyze_with_ollama(&self, content: &str) -> Result<String> {
         let client = ollama_rs::Ollama::default();
         
-        let prompt = format!(
-            "分析以下tmux pane内容，判断opencode是否处于卡住状态：\n\n{}",
-            content
-        );
+        let prompt = self.prompt_for(content);
         
         let request = GenerationRequest::new(self.model.clone(), prompt);
         let response = client.generate(request).await?;
@@ -56,10 +67,7 @@ impl LlmClient {
         
         let request = LlmRequest {
             model: self.model.clone(),
-            prompt: format!(
-                "分析以下tmux pane内容，判断opencode是否处于卡住状态：\n\n{}",
-                content
-            ),
+            prompt: self.prompt_for(content),
             stream: false,
         };
         
@@ -78,10 +86,7 @@ impl LlmClient {
         
         let request = LlmRequest {
             model: self.model.clone(),
-            prompt: format!(
-                "分析以下tmux pane内容，判断opencode是否处于卡住状态：\n\n{}",
-                content
-            ),
+            prompt: self.prompt_for(content),
             stream: false,
         };
         
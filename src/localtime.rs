@@ -0,0 +1,43 @@
+/// 当前本地时间，用于按本地时刻计算等待时间和每日用量在零点清零
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalTime {
+    pub day: i32,             // 年 * 1000 + 一年中的第几天
+    pub seconds_of_day: u64,  // 从零点起的秒数
+    pub utc_offset: i64,      // 与 UTC 的时差（秒），东区为正
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 3600;
+
+#[cfg(unix)]
+pub fn now() -> LocalTime {
+    // localtime_r 是线程安全版本，结果写入自己的 tm
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return utc(now as i64);
+        }
+        LocalTime {
+            day: tm.tm_year * 1000 + tm.tm_yday,
+            seconds_of_day: (tm.tm_hour * 3600 + tm.tm_min * 60 + tm.tm_sec) as u64,
+            utc_offset: tm.tm_gmtoff as i64,
+        }
+    }
+}
+
+/// 非 unix 平台取不到本地时区，按 UTC 计算
+#[cfg(not(unix))]
+pub fn now() -> LocalTime {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    utc(now)
+}
+
+fn utc(now: i64) -> LocalTime {
+    LocalTime {
+        day: now.div_euclid(SECONDS_PER_DAY) as i32,
+        seconds_of_day: now.rem_euclid(SECONDS_PER_DAY) as u64,
+        utc_offset: 0,
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+use regex::{Regex, RegexBuilder};
+use crate::config::{LoopConfig, NormalizeConfig};
+use crate::normalize::Normalizer;
+
+/// 检测到的循环迹象
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoopSignal {
+    RepeatedScreen { repeats: usize },                  // 同一屏幕反复出现
+    RepeatedAction { action: String, repeats: usize },  // 同一命令或修改反复出现
+}
+
+impl fmt::Display for LoopSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopSignal::RepeatedScreen { repeats } => write!(f, "同一屏幕内容出现了 {} 次", repeats),
+            LoopSignal::RepeatedAction { action, repeats } => write!(f, "`{}` 出现了 {} 次", action, repeats),
+        }
+    }
+}
+
+/// agent 循环检测器
+/// 屏幕指纹只在内容变化时记录，因此 spinner 转动或停在同一画面不算重复；A → B → A 这样回到之前的画面才算
+/// 命令和修改行只统计本次新出现的部分：与上次屏幕上的行对齐后，去掉仍然可见的旧行
+pub struct LoopDetector {
+    normalizer: Normalizer,
+    action_patterns: Vec<Regex>,
+    window: Duration,
+    screen_repeats: usize,
+    action_repeats: usize,
+    screens: VecDeque<(Instant, u64)>,
+    actions: VecDeque<(Instant, String)>,  // 规范化后的命令 / 修改
+    last_fingerprint: Option<u64>,
+    last_actions: Vec<(String, String)>,  // 上次屏幕上的命令 / 修改行，按出现顺序
+}
+
+impl LoopDetector {
+    /// 无效的正则会被忽略并给出警告
+    pub fn new(config: &LoopConfig, normalize: &NormalizeConfig) -> Self {
+        let action_patterns = config
+            .action_patterns
+            .iter()
+            .filter_map(|pattern| match RegexBuilder::new(pattern).multi_line(true).build() {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    eprintln!("⚠️ 忽略无效的循环检测正则 {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        LoopDetector {
+            normalizer: Normalizer::new(normalize),
+            action_patterns,
+            window: Duration::from_secs(config.window_sec),
+            screen_repeats: config.screen_repeats.max(2),
+            action_repeats: config.action_repeats.max(2),
+            screens: VecDeque::new(),
+            actions: VecDeque::new(),
+            last_fingerprint: None,
+            last_actions: Vec::new(),
+        }
+    }
+
+    /// 记录一次捕获的可见内容，发现循环时返回迹象
+    pub fn observe(&mut self, content: &str) -> Option<LoopSignal> {
+        self.observe_at(Instant::now(), content)
+    }
+
+    pub fn observe_at(&mut self, now: Instant, content: &str) -> Option<LoopSignal> {
+        while self.screens.front().is_some_and(|(at, _)| now.duration_since(*at) > self.window) {
+            self.screens.pop_front();
+        }
+        while self.actions.front().is_some_and(|(at, _)| now.duration_since(*at) > self.window) {
+            self.actions.pop_front();
+        }
+
+        let fingerprint = self.normalizer.fingerprint(content);
+        if self.last_fingerprint == Some(fingerprint) {
+            return None;
+        }
+        self.last_fingerprint = Some(fingerprint);
+        self.screens.push_back((now, fingerprint));
+
+        let actions = self.extract_actions(content);
+        let new_actions = actions[overlap(&self.last_actions, &actions)..].to_vec();
+        self.last_actions = actions;
+
+        let mut signal = None;
+        for (action, key) in new_actions {
+            self.actions.push_back((now, key.clone()));
+            let repeats = self.actions.iter().filter(|(_, seen)| *seen == key).count();
+            if repeats >= self.action_repeats {
+                signal = Some(LoopSignal::RepeatedAction { action, repeats });
+            }
+        }
+        if signal.is_some() {
+            return signal;
+        }
+
+        let repeats = self.screens.iter().filter(|(_, seen)| *seen == fingerprint).count();
+        (repeats >= self.screen_repeats).then_some(LoopSignal::RepeatedScreen { repeats })
+    }
+
+    /// 已经干预过，清空统计重新开始
+    pub fn reset(&mut self) {
+        self.screens.clear();
+        self.actions.clear();
+    }
+
+    /// 屏幕上的命令 / 修改行，返回 (原文, 规范化后用于比较的内容)
+    fn extract_actions(&self, content: &str) -> Vec<(String, String)> {
+        content
+            .lines()
+            .map(|line| line.trim().trim_matches(|ch| "│║".contains(ch)).trim())
+            .filter_map(|line| {
+                self.action_patterns.iter().find_map(|regex| {
+                    let captures = regex.captures(line)?;
+                    let action = captures.name("action").unwrap_or(captures.get(0)?).as_str().trim().to_string();
+                    let key = self.normalizer.normalize(&action);
+                    Some((action, key))
+                })
+            })
+            .collect()
+    }
+}
+
+/// 上次屏幕末尾与这次屏幕开头重合的行数；屏幕滚动后旧行移到上面，重合部分之后的才是新出现的行
+fn overlap(previous: &[(String, String)], current: &[(String, String)]) -> usize {
+    (0..=previous.len().min(current.len()))
+        .rev()
+        .find(|&len| {
+            previous[previous.len() - len..].iter().map(|(_, key)| key).eq(current[..len].iter().map(|(_, key)| key))
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> LoopDetector {
+        let config = LoopConfig { screen_repeats: 3, action_repeats: 3, ..LoopConfig::default() };
+        LoopDetector::new(&config, &NormalizeConfig::default())
+    }
+
+    #[test]
+    fn test_returning_screen_is_a_loop() {
+        let mut detector = detector();
+        let now = Instant::now();
+        let a = "editing src/lib.rs\n⠋ Thinking… (3s)";
+        let b = "running tests\nFAILED";
+        assert_eq!(detector.observe_at(now, a), None);
+        // 只有 spinner 和计时器变化不算新画面
+        assert_eq!(detector.observe_at(now, "editing src/lib.rs\n⠙ Thinking… (4s)"), None);
+        assert_eq!(detector.observe_at(now, b), None);
+        assert_eq!(detector.observe_at(now, a), None);
+        assert_eq!(detector.observe_at(now, b), None);
+        assert_eq!(detector.observe_at(now, a), Some(LoopSignal::RepeatedScreen { repeats: 3 }));
+    }
+
+    #[test]
+    fn test_only_new_action_lines_count() {
+        let mut detector = detector();
+        let now = Instant::now();
+        // 同一行一直留在屏幕上、随输出向上滚动都只算一次
+        assert_eq!(detector.observe_at(now, "$ cargo test\nrunning 3 tests"), None);
+        assert_eq!(detector.observe_at(now, "$ cargo test\nrunning 3 tests\ntest a ... FAILED"), None);
+        assert_eq!(detector.observe_at(now, "test a ... FAILED\n$ vim src/lib.rs\n$ cargo test"), None);
+        let signal = detector.observe_at(now, "$ cargo test\nerror[E0308]\n$ cargo test\nrunning 3 tests");
+        assert_eq!(signal, Some(LoopSignal::RepeatedAction { action: "cargo test".to_string(), repeats: 3 }));
+    }
+
+    #[test]
+    fn test_window_and_reset() {
+        let mut detector = detector();
+        let start = Instant::now();
+        let later = start + Duration::from_secs(901);
+        for (i, content) in ["a", "b", "a", "b"].iter().enumerate() {
+            detector.observe_at(start + Duration::from_secs(i as u64), content);
+        }
+        // 窗口外的记录被丢弃
+        assert_eq!(detector.observe_at(later, "a"), None);
+        detector.reset();
+        assert_eq!(detector.observe_at(later, "b"), None);
+        assert_eq!(detector.observe_at(later, "a"), None);
+    }
+}
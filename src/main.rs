@@ -1,13 +1,37 @@
-use dotenvy::dotenv;
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
-use std::thread;
+use dotenvy::dotenv;
 use anyhow::Result;
 use tokio::runtime::Runtime;
 
 // 模块声明
 mod config;
 mod args;
+mod backend;
+mod ansi;
 mod tmux;
+mod ssh;
+mod screen;
+mod control;
+mod pipe;
+mod hooks;
+mod discovery;
+mod identity;
+mod liveness;
+mod indicators;
+mod profile;
+mod normalize;
+mod classify;
+mod approval;
+mod localtime;
+mod ratelimit;
+mod loops;
+mod usage;
+mod vt;
+#[cfg(unix)]
+mod pty;
+mod intervention;
 mod activity;
 mod llm;
 mod monitor;
@@ -16,15 +40,29 @@ mod animation;
 mod app;
 
 // 使用声明
-use config::Config;
-use args::Args;
-use tmux::TmuxClient;
+use config::{BackendKind, Config, ProfileKind, TargetConfig};
+use args::{Args, Command};
+use tmux::{PaneListCache, TmuxClient, TmuxSocket};
+use screen::ScreenClient;
+use backend::PaneBackend;
+use control::ControlModeWatcher;
+use pipe::PipePaneBackend;
+use activity::ActivityDetector;
+use classify::StateClassifier;
+use liveness::ExitDetector;
+use monitor::MonitorTarget;
 use clap::Parser;
 
 fn main() -> Result<()> {
-    dotenv().ok();
     let args = Args::parse();
 
+    // tmux hook 在 pane 的工作目录中调用 notify，只转发事件，不加载配置也不启动运行时
+    if let Some(Command::Notify { socket, event, target }) = &args.command {
+        return notify(socket, event, target);
+    }
+
+    dotenv().ok();
+
     // 加载配置
     let mut config = Config::load(&args.config).unwrap_or_else(|_| {
         eprintln!("无法加载配置文件 {}，使用默认配置", args.config);
@@ -33,7 +71,12 @@ fn main() -> Result<()> {
 
     // 使用命令行参数覆盖配置（如果提供）
     if let Some(pane) = &args.pane {
+        // 命令行指定 pane 时只监控该 pane
         config.tmux.pane = pane.clone();
+        config.targets.clear();
+    }
+    if let Some(backend) = &args.backend {
+        config.llm.backend = backend.clone();
     }
     if let Some(interval) = args.interval {
         config.monitoring.interval = interval;
@@ -44,114 +87,353 @@ fn main() -> Result<()> {
     if let Some(max_retry) = args.max_retry {
         config.monitoring.max_retry = max_retry;
     }
+    if args.control_mode {
+        config.monitoring.control_mode = true;
+    }
+    if args.tmux_hooks {
+        config.monitoring.tmux_hooks = true;
+    }
+    if args.pipe_pane {
+        config.tmux.capture.pipe_pane = true;
+    }
+    if let Some(multiplexer) = &args.multiplexer {
+        config.tmux.backend = if multiplexer == "screen" { BackendKind::Screen } else { BackendKind::Tmux };
+        for target in &mut config.targets {
+            target.backend = None;
+        }
+    }
+    if let Some(profile) = &args.profile {
+        config.select_profile(ProfileKind::from_name(profile));
+    }
+    if args.discover {
+        config.tmux.pane = "auto".to_string();
+        config.targets.clear();
+    }
+
+    // 按全局配置档补全后的配置，用于自动发现和 list-panes；各目标的配置档在展开目标时再应用
+    let mut defaults = config.clone();
+    defaults.apply_profile();
+
+    let tmux_client = build_tmux_client(&config);
+    let rt = Runtime::new()?;
+
+    match args.command {
+        Some(Command::ListPanes { all }) => return rt.block_on(list_panes(&tmux_client, &defaults, all)),
+        Some(Command::Run { log, command }) => return run_supervised(defaults, &command, &log),
+        Some(Command::Notify { .. }) | None => {}
+    }
+
+    // 自动发现运行 agent 的 pane，每个选中的 pane 作为一个监控目标
+    if config.tmux.pane == "auto" && config.targets.is_empty() {
+        let panes = discovery::find_agent_panes(rt.block_on(tmux_client.list_panes())?, &defaults.tmux.agent_commands);
+        let chosen = discovery::choose_panes(&panes);
+        if chosen.is_empty() {
+            return Err(anyhow::anyhow!("未发现运行 {:?} 的 tmux pane", defaults.tmux.agent_commands));
+        }
+        for pane in chosen {
+            println!("🔍 自动发现 agent pane: {} ({})", pane.metadata.pane_id, pane.target);
+            config.targets.push(TargetConfig {
+                name: Some(format!("{} {}", pane.metadata.pane_id, pane.target)),
+                pane: pane.metadata.pane_id.clone(),
+                backend: Some(BackendKind::Tmux),
+                profile: None,
+                monitoring: None,
+                intervention: None,
+                respawn: None,
+                normalize: None,
+                states: None,
+                approval: None,
+                rate_limit: None,
+                loop_detection: None,
+                usage: None,
+                indicators: None,
+                idle_prompts: None,
+                remote: None,
+                socket_name: None,
+                socket_path: None,
+            });
+        }
+    }
+
+    let targets = config.resolve_targets();
 
     println!("Opencode-Watch 启动成功");
-    println!("监控 tmux pane: {}", config.tmux.pane);
-    println!("监控间隔: {} 秒", config.monitoring.interval);
-    println!("卡住判定: {} 秒", config.monitoring.stuck_sec);
-    println!("最大重试: {} 次", config.monitoring.max_retry);
-    println!("干预指令数量: {} 个", config.intervention.commands.len());
+    println!("监控目标数量: {} 个", targets.len());
+    for (name, target) in &targets {
+        println!(
+            "  - {}: {:?} pane {}，配置档 {}，间隔 {} 秒，卡住判定 {} 秒，最大重试 {} 次，干预指令 {} 个，控制模式{}",
+            name,
+            target.tmux.backend,
+            target.tmux.pane,
+            target.profile.unwrap_or_default().name(),
+            target.monitoring.interval,
+            target.monitoring.stuck_sec,
+            target.monitoring.max_retry,
+            target.intervention.commands.len(),
+            if target.monitoring.control_mode { "开启" } else { "关闭" }
+        );
+    }
     println!("按 Ctrl+C 退出");
 
-    let tmux_client = TmuxClient::new();
-    let mut stuck_count = 0;
-    let mut command_index = 0;
-    
     // 加载全局配置
     if let Err(e) = config::Config::load_global(&args.config) {
         eprintln!("警告: 无法加载全局配置: {}", e);
     }
 
-    // 创建Tokio运行时（虽然不再需要LLM，但保留以备将来使用）
-    let _rt = Runtime::new().unwrap();
-
-    // 简单监控循环
-    loop {
-        println!("\n=== 检查时间: {:?} ===", std::time::SystemTime::now());
-        
-        // 检查pane是否存在
-        if !tmux_client.check_pane_exists(&config.tmux.pane) {
-            eprintln!("❌ tmux pane {} 不存在", config.tmux.pane);
-            break;
+    // 为每个目标准备独立的后端，控制模式下各自附加只读控制客户端
+    // watchers 需要在监控期间保持存活，析构时会关闭控制连接
+    // pipes 同理，析构时关闭 pipe-pane
+    let mut watchers = Vec::new();
+    let mut pipes = Vec::new();
+    #[cfg(unix)]
+    let mut hook_set = hooks::HookSet::default();
+    let mut pane_caches = HashMap::new();
+    let mut monitor_targets = Vec::new();
+    for (name, target_config) in targets {
+        let mut client = build_backend(&target_config, &mut pane_caches);
+        let remote = build_tmux_client(&target_config)
+            .remote_destination()
+            .filter(|_| target_config.tmux.backend == BackendKind::Tmux);
+        if let Some(destination) = &remote {
+            println!("[{}] 🌐 通过 SSH 监控 {} 上的 tmux", name, destination);
+        }
+        if let Some(socket) = TmuxSocket::from_config(&target_config.tmux).filter(|_| target_config.tmux.backend == BackendKind::Tmux) {
+            println!("[{}] 🔌 使用 tmux 服务器 {}", name, socket);
         }
 
-        // 捕获内容
-        match tmux_client.capture_pane_content(&config.tmux.pane) {
-            Ok(content) => {
-                let lines: Vec<&str> = content.lines().collect();
-                println!("📄 捕获到 {} 行内容", lines.len());
-                
-                // 显示最后几行
-                let last_lines = lines.iter().rev().take(3).collect::<Vec<_>>();
-                println!("📝 最后3行内容:");
-                for line in last_lines.iter().rev() {
-                    println!("   {}", line);
+        // pipe-pane：由原始输出流还原屏幕，输出信号来自日志读取线程
+        let pipe = if !target_config.tmux.capture.pipe_pane {
+            None
+        } else if target_config.tmux.backend != BackendKind::Tmux || remote.is_some() {
+            eprintln!("[{}] ⚠️ pipe-pane 仅支持本机 tmux，使用 capture-pane", name);
+            None
+        } else {
+            let capture = &target_config.tmux.capture;
+            let inner = build_tmux_client(&target_config);
+            let attached = PipePaneBackend::attach(inner, &target_config.tmux.pane, capture.log_dir.as_deref(), capture.scrollback as usize);
+            match rt.block_on(attached) {
+                Ok(pipe) => {
+                    println!("[{}] 📼 已开启 pipe-pane，原始输出写入 {}（{}）", name, pipe.log_path().display(), pipe.describe_screen());
+                    let pipe = Arc::new(pipe);
+                    client = Box::new(pipe.clone());
+                    pipes.push(pipe.clone());
+                    Some(pipe)
                 }
-                
-                // 检查opencode工作状态 - 基于working和esc interrupt标识
-                let has_working = content.contains("working");
-                let has_esc_interrupt = content.contains("esc interrupt");
-                let is_active = has_working || has_esc_interrupt;
-                
-                if is_active {
-                    // 检测到working或esc interrupt，正在工作
-                    stuck_count = 0;
-                    println!("✅ 检测到工作状态 (working: {}, esc interrupt: {})", has_working, has_esc_interrupt);
-                } else {
-                    // 没有检测到working或esc interrupt，可能卡住
-                    stuck_count += 1;
-                    println!("⏸️  工作标识消失 (第{}次)", stuck_count);
-                    
-                    if stuck_count >= 3 {
-                        println!("🚨 检测到卡住状态!");
-                        
-                        // 获取下一个干预指令
-                        let (command, new_index) = config.get_next_intervention_command(command_index);
-                        command_index = new_index;
-                        
-                        println!("🔧 尝试干预指令 [{}/{}]: '{}'", command_index + 1, config.intervention.commands.len(), command);
-                        
-                        // 直接执行干预（基于明确的working/esc interrupt逻辑，不需要LLM分析）
-                        if let Err(e) = tmux_client.send_keys(&config.tmux.pane, &command) {
-                            eprintln!("❌ 发送指令失败: {}", e);
-                        } else {
-                            println!("✅ 已发送指令: '{}'", command);
-                            
-                            // 等待指令输入完成，然后发送回车
-                            let command_delay = Duration::from_millis(config.intervention.command_delay_ms);
-                            let enter_delay = Duration::from_millis(config.intervention.enter_delay_ms);
-                            
-                            // 特殊处理C-c命令（不需要回车）
-                            if command == "C-c" {
-                                println!("✅ Ctrl+C已发送，无需回车");
-                            } else if command.starts_with('/') {
-                                println!("✅ 命令指令已发送，无需回车");
-                            } else {
-                                println!("⏳ 等待 {}ms 后发送回车...", config.intervention.enter_delay_ms);
-                                thread::sleep(command_delay);
-                                
-                                if let Err(e) = tmux_client.send_keys(&config.tmux.pane, "Enter") {
-                                    eprintln!("❌ 发送回车失败: {}", e);
-                                } else {
-                                    thread::sleep(enter_delay - command_delay);
-                                    println!("✅ 已发送回车，指令执行完成");
-                                }
-                            }
-                        }
-                        
-                        // 重置计数器，继续监控
-                        stuck_count = 0;
-                    }
+                Err(e) => {
+                    eprintln!("[{}] ⚠️ 无法开启 pipe-pane，使用 capture-pane: {}", name, e);
+                    None
                 }
             }
-            Err(e) => {
-                eprintln!("❌ 捕获内容失败: {}", e);
+        };
+
+        let output_signal = if let Some(pipe) = &pipe {
+            if target_config.monitoring.control_mode {
+                println!("[{}] 已使用 pipe-pane 输出信号，不再启动控制模式", name);
+            }
+            Some(pipe.signal())
+        } else if target_config.monitoring.control_mode && target_config.tmux.backend != BackendKind::Tmux {
+            eprintln!("[{}] ⚠️ 控制模式仅支持 tmux，使用轮询", name);
+            None
+        } else if target_config.monitoring.control_mode {
+            let pane_id = rt
+                .block_on(client.resolve_pane(&target_config.tmux.pane))
+                .unwrap_or_else(|_| target_config.tmux.pane.clone());
+            match ControlModeWatcher::spawn(&build_tmux_client(&target_config), &pane_id) {
+                Ok(watcher) => {
+                    let signal = watcher.signal();
+                    watchers.push(watcher);
+                    Some(signal)
+                }
+                Err(e) => {
+                    eprintln!("[{}] ⚠️ 无法启动 tmux 控制模式，退回轮询: {}", name, e);
+                    None
+                }
             }
+        } else {
+            None
+        };
+
+        // tmux hook：静默和退出事件由 tmux 主动推送
+        let hook_events = if !target_config.monitoring.tmux_hooks {
+            None
+        } else if target_config.tmux.backend != BackendKind::Tmux || remote.is_some() || cfg!(not(unix)) {
+            eprintln!("[{}] ⚠️ tmux hook 仅支持类 Unix 系统上的本机 tmux", name);
+            None
+        } else {
+            #[cfg(unix)]
+            let installed = rt
+                .block_on(client.resolve_pane(&target_config.tmux.pane))
+                .and_then(|pane_id| hook_set.install(&pane_id, &target_config, build_tmux_client(&target_config)));
+            #[cfg(not(unix))]
+            let installed: Result<_> = Err(anyhow::anyhow!("不支持"));
+            match installed {
+                Ok(events) => {
+                    println!("[{}] 🪝 已安装 tmux hook", name);
+                    Some(events)
+                }
+                Err(e) => {
+                    eprintln!("[{}] ⚠️ 无法安装 tmux hook，仅使用轮询: {}", name, e);
+                    None
+                }
+            }
+        };
+
+        monitor_targets.push(MonitorTarget {
+            name,
+            config: target_config,
+            backend: client,
+            output_signal,
+            hook_events,
+        });
+    }
+
+    // 创建Tokio运行时，每个目标作为独立任务运行
+    // 收到退出信号时正常返回，让控制连接和 tmux hook 得到清理
+    let result = rt.block_on(async {
+        tokio::select! {
+            result = monitor::supervise(monitor_targets) => result,
+            _ = shutdown_signal() => {
+                println!("收到退出信号，正在清理");
+                Ok(())
+            }
+        }
+    });
+    drop(watchers);
+    drop(pipes);
+    #[cfg(unix)]
+    drop(hook_set);
+    result
+}
+
+/// 等待 Ctrl+C 或 SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// 根据配置创建 tmux 客户端，配置了 remote 时经 SSH 操作远程主机
+fn build_tmux_client(config: &Config) -> TmuxClient {
+    TmuxClient::new()
+        .with_capture(config.tmux.capture.clone())
+        .with_remote(config.tmux.remote.clone())
+        .with_socket(TmuxSocket::from_config(&config.tmux))
+        .with_timeout(Duration::from_millis(config.tmux.command_timeout_ms))
+}
+
+/// 根据目标配置创建终端后端
+/// pane_caches 按 tmux 服务器保存共用的 pane 列表，开启 batch_poll 的目标从中取用
+fn build_backend(config: &Config, pane_caches: &mut HashMap<String, Arc<PaneListCache>>) -> Box<dyn PaneBackend + Send + Sync> {
+    match config.tmux.backend {
+        BackendKind::Tmux if config.monitoring.batch_poll => {
+            let client = build_tmux_client(config);
+            // 缓存有效期取检查间隔的一半：同时到期的目标共用一次查询，下一轮检查一定重新查询
+            let max_age = Duration::from_secs(config.monitoring.interval) / 2;
+            let cache = pane_caches
+                .entry(client.server_key())
+                .or_insert_with(|| Arc::new(PaneListCache::new(max_age)))
+                .clone();
+            Box::new(client.with_pane_cache(cache))
         }
-        
-        // 等待下次检查
-        thread::sleep(Duration::from_secs(config.monitoring.interval));
+        BackendKind::Tmux => Box::new(build_tmux_client(config)),
+        BackendKind::Screen => Box::new(ScreenClient::new().with_scrollback(config.tmux.capture.scrollback > 0)),
+    }
+}
+
+/// run 子命令：在内置伪终端中运行 agent，转发用户终端，同时执行卡住检测和干预
+#[cfg(unix)]
+fn run_supervised(mut config: Config, command: &[String], log_path: &str) -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::IsTerminal;
+
+    let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+    let log = OpenOptions::new().create(true).append(true).open(log_path)?;
+    eprintln!("🚀 在内置终端中运行 {:?}，监控日志写入 {}", command, log_path);
+
+    // 终端交给 agent 使用，本进程的日志全部写入文件
+    let terminal = pty::redirect_output_to_log(&log)?;
+    let session = Arc::new(pty::PtySession::spawn(command, rows, cols, Some(terminal))?);
+
+    let raw_mode = std::io::stdin().is_terminal() && crossterm::terminal::enable_raw_mode().is_ok();
+    session.relay_stdin();
+    session.follow_terminal_size();
+
+    config.tmux.pane = command[0].clone();
+    let target = MonitorTarget {
+        name: command[0].clone(),
+        output_signal: Some(session.signal()),
+        hook_events: None,
+        config,
+        backend: session.clone(),
+    };
+
+    let rt = Runtime::new()?;
+    let result = rt.block_on(monitor::run_monitoring_loop(target));
+
+    if raw_mode {
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+    let status = session.wait()?;
+    println!("agent 已退出: {}", status);
+    result?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+#[cfg(not(unix))]
+fn run_supervised(_config: Config, _command: &[String], _log_path: &str) -> Result<()> {
+    Err(anyhow::anyhow!("run 模式仅支持类 Unix 系统"))
+}
+
+/// notify 子命令：由安装的 tmux hook 调用
+#[cfg(unix)]
+fn notify(socket: &std::path::Path, event: &str, target: &str) -> Result<()> {
+    hooks::send_notification(socket, event, target)
+}
+
+#[cfg(not(unix))]
+fn notify(_socket: &std::path::Path, _event: &str, _target: &str) -> Result<()> {
+    Err(anyhow::anyhow!("tmux hook 仅支持类 Unix 系统"))
+}
+
+/// list-panes 子命令：列出 agent pane 及其检测到的状态
+async fn list_panes(tmux_client: &TmuxClient, config: &Config, all: bool) -> Result<()> {
+    let panes = tmux_client.list_panes().await?;
+    let panes = if all {
+        panes
+    } else {
+        discovery::find_agent_panes(panes, &config.tmux.agent_commands)
+    };
+
+    if panes.is_empty() {
+        println!("未发现运行 {:?} 的 tmux pane", config.tmux.agent_commands);
+        return Ok(());
     }
-    
+
+    let classifier = StateClassifier::new(&config.states, config.idle_prompts.as_deref().unwrap_or_default());
+    let exits = ExitDetector::new(&config.respawn.shell_prompts);
+    println!("{:<6} {:<16} {:<12} {:<8} 目录", "PANE", "位置", "命令", "状态");
+    for pane in &panes {
+        let state = match ActivityDetector::new(&config.indicators, &config.regions).check_activity(tmux_client, &pane.metadata.pane_id).await {
+            Ok(result) => {
+                let exit = exits.detect(&pane.metadata, &result.last_content);
+                classifier.classify(&result, exit.as_ref()).state.label()
+            }
+            Err(_) => "无法捕获",
+        };
+        println!(
+            "{:<6} {:<16} {:<12} {:<8} {}",
+            pane.metadata.pane_id, pane.target, pane.metadata.current_command, state, pane.metadata.current_path
+        );
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,73 +1,1332 @@
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
 use tokio::time::sleep;
-use crate::config::Config;
-use crate::tmux::TmuxClient;
-use crate::activity::ActivityDetector;
+use crate::config::{BudgetAction, Config, RespawnMethod};
+use crate::backend::{PaneBackend, PaneMetadata};
+use crate::activity::{ActivityDetector, ActivityResult};
+use crate::control::OutputSignal;
+use crate::intervention::Intervention;
+use crate::identity::PaneFingerprint;
+use crate::hooks::{HookEvent, HookEvents};
+use crate::liveness::{AgentExit, ExitDetector};
+use crate::classify::{AgentState, Classification, StateClassifier};
+use crate::approval::{ApprovalPolicy, Decision};
+use crate::normalize::Normalizer;
+use crate::ratelimit::RetryParser;
+use crate::loops::{LoopDetector, LoopSignal};
+use crate::usage::{format_count, UsageTracker};
+use crate::llm::LlmClient;
+use crate::tmux::TmuxError;
 use anyhow::Result;
 
-pub async fn run_monitoring_loop(
-    config: &Config,
-    _last_active: &mut Instant,
-    _retry_count: &mut usize,
-) -> Result<()> {
-    let tmux_client = TmuxClient::new();
-    let mut activity_detector = ActivityDetector::new();
-    let mut last_active_local = Instant::now();
-    let mut retry_count_local = 0;
-    
-    loop {
-        sleep(Duration::from_secs(config.monitoring.interval)).await;
-        
-        // 检查tmux pane是否存在
-        if !tmux_client.check_pane_exists(&config.tmux.pane) {
-            eprintln!("tmux pane {} 不存在", config.tmux.pane);
-            continue;
-        }
-        
-        // 检查活动状态
-        match activity_detector.check_activity(&config.tmux.pane) {
-            Ok(is_active) => {
-                if is_active {
-                    // 检测到working或esc interrupt标识，正在工作
-                    last_active_local = Instant::now();
-                    retry_count_local = 0;
-                    println!("检测到工作状态 (working/esc interrupt)");
-                } else {
-                    // 没有检测到working或esc interrupt标识，可能卡住
-                    let inactive_duration = last_active_local.elapsed();
-                    if inactive_duration.as_secs() > config.monitoring.stuck_sec {
-                        println!("检测到工作标识消失 {} 秒，确认卡住", inactive_duration.as_secs());
-                        
-                        // 直接干预，不需要LLM分析，因为逻辑很明确：
-                        // working和esc interrupt都消失 = 卡住
-                        retry_count_local += 1;
-                        if retry_count_local <= config.monitoring.max_retry {
-                            println!("尝试干预 (第{}次)", retry_count_local);
-                            
-                            // 发送当前配置的命令列表中的下一个命令
-                            let command_index = (retry_count_local - 1) % config.intervention.commands.len();
-                            let command = &config.intervention.commands[command_index];
-                            println!("发送命令: {}", command);
-                            
-                            tmux_client.send_keys(&config.tmux.pane, command)?;
-                            
-                            // 如果配置了回车键延迟，等待后发送回车
-                            if config.intervention.enter_delay_ms > 0 {
-                                sleep(Duration::from_millis(config.intervention.enter_delay_ms as u64)).await;
-                                tmux_client.send_keys(&config.tmux.pane, "Enter")?;
-                            }
-                        } else {
-                            println!("达到最大重试次数，停止干预");
-                            break;
-                        }
-                    }
+/// 连续多少次检测不到工作标识后判定为卡住
+const STUCK_THRESHOLD: usize = 3;
+
+/// 日志中显示最近多长时间的 token 增量
+const RECENT_USAGE_WINDOW: Duration = Duration::from_secs(600);
+
+/// 等待 LLM 分析结果的上限
+const LLM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 后端暂时不可用时重试间隔的上限
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// 单次检查的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum TickOutcome {
+    PaneMissing,                  // pane 不存在
+    Active,                       // 检测到工作状态
+    Idle { stuck_count: usize },  // 工作标识消失，尚未达到卡住阈值
+    Intervened { command: String },  // 判定卡住并发送了干预指令
+    PaneReplaced,                 // pane 主进程已变化（pane 标识被回收），拒绝输入
+    CaptureFailed,                // 捕获内容失败
+    BackendUnavailable,           // tmux 服务器未运行或命令超时，稍后重试
+    BackendFatal { error: String },  // 未安装 tmux 或没有权限，无法继续监控
+    AgentExited,                  // agent 已退出且没有（或无法）重新启动，不再向 shell 发送干预指令
+    Respawned { command: String },  // agent 已退出，执行了重启命令
+    Held { state: AgentState },   // 达到卡住阈值，但该状态按配置不干预（例如等待权限确认）
+    PermissionAnswered { request: String, approved: bool },  // 按审批规则自动回答了权限确认
+    Escalated { request: String },  // 权限请求没有匹配的规则，交给人工处理
+    BackedOff { delay_sec: u64 },   // 被限流，暂停干预 delay_sec 秒
+    Suspended { remaining_sec: u64 },  // 仍在限流等待中，不干预
+    Resumed { command: String },    // 限流等待结束，发送了恢复指令
+    LoopDetected { signal: LoopSignal },  // agent 在重复同样的操作，发送了循环干预指令
+    BudgetExceeded { reason: String },  // 用量达到预算，按 on_exceeded 处理并开始暂停干预
+    BudgetPaused,                   // 仍超出预算，不干预
+    RetriesExhausted,               // 已连续干预 max_retry 次仍未恢复工作，不再干预
+}
+
+/// 按后端错误的类型决定本次检查的结果
+fn outcome_for_error(error: &anyhow::Error) -> TickOutcome {
+    match error.downcast_ref::<TmuxError>() {
+        Some(TmuxError::PaneGone(_)) => TickOutcome::PaneMissing,
+        Some(TmuxError::ServerNotRunning(_) | TmuxError::TimedOut { .. }) => TickOutcome::BackendUnavailable,
+        Some(e @ (TmuxError::NotInstalled | TmuxError::PermissionDenied(_))) => TickOutcome::BackendFatal { error: e.to_string() },
+        _ => TickOutcome::CaptureFailed,
+    }
+}
+
+/// 限流提示行及其在屏幕上出现的次数；同样的提示再次输出时次数增加，视为新的提示
+fn rate_limit_hint(classification: &Classification, content: &str) -> (String, usize) {
+    let line = classification.evidence.clone().unwrap_or_default();
+    let count = content.lines().filter(|candidate| candidate.trim() == line).count();
+    (line, count)
+}
+
+/// 后端连续不可用时的重试间隔：从检查间隔开始逐次翻倍，不超过 MAX_BACKOFF
+fn backoff_delay(interval: Duration, failures: u32) -> Duration {
+    let base = interval.max(Duration::from_secs(1));
+    base.saturating_mul(1 << failures.saturating_sub(1).min(16)).min(MAX_BACKOFF)
+}
+
+/// 触发本次检查的原因
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TickTrigger {
+    Interval,  // 定时检查
+    Output,    // 控制模式收到 pane 输出
+    Silence,   // tmux 推送 alert-silence：pane 已静默 stuck_sec 秒
+}
+
+/// 单个 pane 的监控会话
+/// 持有后端、检测器以及卡住计数和指令索引
+pub struct MonitorSession<B: PaneBackend> {
+    backend: B,
+    label: String,  // 日志前缀中显示的目标名称
+    pane: String,
+    config: Config,
+    detector: ActivityDetector,
+    classifier: StateClassifier,
+    approval: ApprovalPolicy,
+    last_permission: Option<String>,  // 已经处理（回答或上报）过的权限请求，对话框未消失时不再重复处理
+    retry: RetryParser,
+    suspended_until: Option<Instant>,  // 限流等待结束的时间，此前不干预
+    resumed_hint: Option<(String, usize)>,  // 发送恢复指令时屏幕上的限流提示行及其出现次数，没有变化时不再重新等待
+    loops: LoopDetector,
+    usage: UsageTracker,
+    llm: Option<LlmClient>,  // 配置了 LLM 后端时，卡住后分析 pane 内容
+    budget_exceeded: Option<String>,  // 超出的预算，此期间不干预
+    stuck_count: usize,
+    unknown_warned: bool,  // 已经提示过卡住后无法识别状态，识别出状态后清除
+    retry_count: usize,  // 连续干预次数，agent 恢复工作后清零，超过 max_retry 时停止干预
+    command_index: usize,
+    state_command_index: HashMap<AgentState, usize>,  // on_state 中各状态指令列表的下一条位置
+    output_signal: Option<Arc<OutputSignal>>,
+    captured_bytes: Option<u64>,  // 上次捕获时的输出字节计数
+    captured_activity: Option<(u64, bool)>,  // 上次捕获时的活动时间，以及该时间戳是否已经连续捕获过两次
+    fingerprint: Option<PaneFingerprint>,  // 开始监控时记录的 pane 主进程指纹
+    exits: ExitDetector,
+    respawn_attempts: usize,  // 连续重启次数，agent 恢复工作后清零
+    respawned_at: Option<Instant>,  // 上次重启的时间，grace_sec 内不检测退出
+}
+
+impl<B: PaneBackend> MonitorSession<B> {
+    pub fn new(backend: B, config: Config) -> Self {
+        let exits = ExitDetector::new(&config.respawn.shell_prompts);
+        let detector = ActivityDetector::new(&config.indicators, &config.regions).with_normalizer(Normalizer::new(&config.normalize));
+        let approval = ApprovalPolicy::new(&config.approval);
+        let usage = UsageTracker::new(&config.usage);
+        let loops = LoopDetector::new(&config.loop_detection, &config.normalize);
+        let llm = (config.llm.backend != "none")
+            .then(|| LlmClient::new(&config.llm.backend, &config.llm.model).with_prompt(&config.agent_profile().llm_prompt));
+        let retry = RetryParser::new(&config.rate_limit, config.states.search_lines);
+        let classifier = StateClassifier::new(&config.states, config.idle_prompts.as_deref().unwrap_or_default());
+        MonitorSession {
+            backend,
+            label: config.tmux.pane.clone(),
+            pane: config.tmux.pane.clone(),
+            config,
+            detector,
+            classifier,
+            approval,
+            last_permission: None,
+            retry,
+            suspended_until: None,
+            resumed_hint: None,
+            loops,
+            usage,
+            llm,
+            budget_exceeded: None,
+            stuck_count: 0,
+            unknown_warned: false,
+            retry_count: 0,
+            command_index: 0,
+            state_command_index: HashMap::new(),
+            output_signal: None,
+            captured_bytes: None,
+            captured_activity: None,
+            fingerprint: None,
+            exits,
+            respawn_attempts: 0,
+            respawned_at: None,
+        }
+    }
+
+    /// 设置日志中显示的目标名称
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = label.to_string();
+        self
+    }
+
+    /// 使用控制模式输出信号：没有新输出时复用上次捕获的内容
+    pub fn with_output_signal(mut self, signal: Arc<OutputSignal>) -> Self {
+        self.output_signal = Some(signal);
+        self
+    }
+
+    /// 记录 pane 当前的主进程指纹，之后每次干预前都会核对
+    pub async fn record_fingerprint(&mut self) {
+        match self.backend.pane_fingerprint(&self.pane).await {
+            Ok(Some(fingerprint)) => {
+                println!("[{}] 🔒 记录 pane 指纹: pid {} (启动时间: {:?})", self.label, fingerprint.pid, fingerprint.start_time);
+                self.fingerprint = Some(fingerprint);
+            }
+            Ok(None) => println!("[{}] ⚠️ 后端不提供 pane 主进程信息，跳过指纹校验", self.label),
+            Err(e) => eprintln!("[{}] ⚠️ 无法获取 pane 指纹: {}", self.label, e),
+        }
+    }
+
+    /// 记录开始监控时 pane 的前台进程，之后它回到 shell 即判定 agent 已退出
+    pub async fn record_agent(&mut self) {
+        match self.backend.pane_metadata(&self.pane).await {
+            Ok(meta) => {
+                println!(
+                    "[{}] 🖥️  pane {} 前台进程: {} (pid: {:?}, {}x{}, 标题: {}, 目录: {})",
+                    self.label, meta.pane_id, meta.current_command, meta.pid, meta.width, meta.height, meta.title, meta.current_path
+                );
+                self.exits.record_agent(&meta.current_command);
+                if self.exits.agent_command().is_none() {
+                    println!("[{}] ⚠️ 前台进程不是 agent，只根据 pane_dead 和 shell 提示符判断退出", self.label);
                 }
             }
+            Err(e) => eprintln!("[{}] ⚠️ 无法获取 pane 元数据: {}", self.label, e),
+        }
+    }
+
+    /// 是否配置了重启命令
+    pub fn respawn_enabled(&self) -> bool {
+        self.config.respawn.command.is_some()
+    }
+
+    /// 核对 pane 主进程指纹，未记录指纹时视为一致
+    async fn fingerprint_matches(&self) -> Result<bool> {
+        let Some(expected) = &self.fingerprint else {
+            return Ok(true);
+        };
+        match self.backend.pane_fingerprint(&self.pane).await? {
+            Some(current) if current == *expected => Ok(true),
+            current => {
+                eprintln!("[{}] 🛑 pane 主进程已变化: 记录为 {:?}，当前为 {:?}", self.label, expected, current);
+                Ok(false)
+            }
+        }
+    }
+
+    /// 执行一次检查：捕获内容、判断状态、必要时干预
+    /// 由输出触发的检查只会重置卡住计数，不会累加
+    pub async fn tick(&mut self, trigger: TickTrigger) -> TickOutcome {
+        println!("\n[{}] === 检查时间: {:?} ({:?}) ===", self.label, std::time::SystemTime::now(), trigger);
+
+        // 检查pane是否存在
+        match self.backend.check_pane_exists(&self.pane).await {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!("[{}] ❌ pane {} 不存在", self.label, self.pane);
+                return TickOutcome::PaneMissing;
+            }
             Err(e) => {
-                eprintln!("活动检测失败: {}", e);
+                eprintln!("[{}] ❌ 无法确认 pane 是否存在: {}", self.label, e);
+                return outcome_for_error(&e);
+            }
+        }
+
+        // 元数据用于判断是否有新输出以及 agent 是否已退出；取不到时照常捕获
+        let metadata = match self.backend.pane_metadata(&self.pane).await {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                eprintln!("[{}] ⚠️ 无法获取 pane 元数据: {}", self.label, e);
+                None
+            }
+        };
+
+        // 捕获内容
+        let result = match self.capture(metadata.as_ref().and_then(|m| m.activity)).await {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("[{}] ❌ 捕获内容失败: {}", self.label, e);
+                return outcome_for_error(&e);
+            }
+        };
+
+        let lines: Vec<&str> = result.last_content.lines().collect();
+        println!(
+            "[{}] 📄 捕获到 {} 行内容（滚动历史 {} 行，{}，指纹 {:016x}）",
+            self.label,
+            lines.len(),
+            result.screen.visible_start,
+            if result.content_changed { "有变化" } else { "无变化" },
+            result.fingerprint
+        );
+
+        if let Some(rate) = result.output_rate {
+            println!("[{}] 📈 输出速率: {:.0} 字节/秒", self.label, rate);
+        }
+
+        // 显示最后几行
+        let last_lines = lines.iter().rev().take(3).collect::<Vec<_>>();
+        println!("[{}] 📝 最后3行内容:", self.label);
+        for line in last_lines.iter().rev() {
+            println!("[{}]    {}", self.label, line);
+        }
+
+        // 超出预算时连重启也不做
+        if self.config.usage.enabled {
+            if let Some(outcome) = self.track_usage(&result.last_content).await {
+                return outcome;
+            }
+        }
+
+        // agent 退出后屏幕上可能残留工作标识，先确认它还在运行
+        if let Some(exit) = metadata.and_then(|metadata| self.detect_exit(&metadata, &result.last_content)) {
+            return self.handle_exit(exit).await;
+        }
+
+        let classification = self.classifier.classify(&result, None);
+        if classification.state != AgentState::Unknown {
+            self.unknown_warned = false;
+        }
+        if classification.state != AgentState::AwaitingPermission {
+            self.last_permission = None;
+        } else if self.config.approval.enabled {
+            // 权限确认不必等到卡住阈值，按规则立即回答
+            if let Some(outcome) = self.answer_permission(&result.last_content).await {
+                return outcome;
+            }
+        }
+        // 循环中的 agent 往往一直显示工作标识，屏幕也在变化，卡住检测发现不了
+        let loop_signal = if self.config.loop_detection.enabled { self.loops.observe(&result.last_content) } else { None };
+        if let Some(signal) = loop_signal {
+            let interruptible = matches!(classification.state, AgentState::Working | AgentState::IdleAtPrompt | AgentState::ErrorShown);
+            if interruptible && self.suspended_until.is_none() {
+                return self.break_loop(signal).await;
+            }
+        }
+
+        if classification.state == AgentState::Working {
+            // 检测到活动标识，正在工作
+            if self.suspended_until.take().is_some() {
+                println!("[{}] ✅ agent 已自行恢复工作，取消限流等待", self.label);
+            }
+            self.stuck_count = 0;
+            self.retry_count = 0;
+            self.respawn_attempts = 0;
+            println!("[{}] ✅ 检测到工作状态 (标识: {})", self.label, result.indicators.positive.join(", "));
+            return TickOutcome::Active;
+        }
+
+        if self.config.rate_limit.enabled {
+            if let Some(outcome) = self.back_off(&classification, &result.last_content).await {
+                return outcome;
+            }
+        }
+
+        if trigger == TickTrigger::Output {
+            // 有输出说明 pane 仍在变化，不计入卡住次数
+            return TickOutcome::Idle { stuck_count: self.stuck_count };
+        }
+
+        // tmux 已经确认静默了 stuck_sec 秒，直接进入卡住判定
+        if trigger == TickTrigger::Silence {
+            self.stuck_count = self.stuck_count.max(STUCK_THRESHOLD - 1);
+        }
+
+        // 没有检测到活动标识，可能卡住
+        self.stuck_count += 1;
+        match &result.indicators.negative {
+            Some(name) => println!("[{}] ⏸️  出现反向标识 {} (第{}次)", self.label, name, self.stuck_count),
+            None => println!("[{}] ⏸️  工作标识消失 (第{}次)", self.label, self.stuck_count),
+        }
+        match &classification.evidence {
+            Some(evidence) => println!("[{}] 🏷️  状态: {} ({})", self.label, classification.state, evidence),
+            None => println!("[{}] 🏷️  状态: {}", self.label, classification.state),
+        }
+
+        if self.stuck_count < STUCK_THRESHOLD {
+            return TickOutcome::Idle { stuck_count: self.stuck_count };
+        }
+
+        println!("[{}] 🚨 检测到卡住状态! ({})", self.label, classification.state);
+        self.analyze_with_llm(&result.last_content).await;
+        // agent 可能停在确认对话框或运行中的命令上，看到输入提示符之前不输入指令
+        if classification.state == AgentState::Unknown {
+            println!("[{}] ⌛ 未看到输入提示符，暂不发送干预指令", self.label);
+            if !self.unknown_warned {
+                self.unknown_warned = true;
+                let last_line = result.last_content.lines().map(str::trim).rev().find(|line| !line.is_empty()).unwrap_or_default();
+                eprintln!(
+                    "[{}] ⚠️ 已卡住但无法识别 agent 状态：屏幕末尾没有匹配 idle_prompts 的输入提示符，也不像其它已知状态（最后一行: {:?}）；如果 agent 其实在等待输入，请检查配置档或 idle_prompts",
+                    self.label, last_line
+                );
+            }
+            // 计数停在阈值，之后每次检查仍会重新判定，但不再无限增长
+            let stuck_count = self.stuck_count;
+            self.stuck_count = STUCK_THRESHOLD - 1;
+            return TickOutcome::Idle { stuck_count };
+        }
+        if self.config.commands_for(classification.state).is_none() {
+            println!("[{}] ✋ {}，按配置不发送干预指令", self.label, classification.state);
+            self.stuck_count = 0;
+            return TickOutcome::Held { state: classification.state };
+        }
+        if self.retry_count >= self.config.monitoring.max_retry {
+            if self.retry_count == self.config.monitoring.max_retry {
+                println!("[{}] 🛑 达到最大重试次数 ({})，停止干预，直到 agent 恢复工作", self.label, self.config.monitoring.max_retry);
+                self.retry_count += 1;
+            }
+            self.stuck_count = 0;
+            return TickOutcome::RetriesExhausted;
+        }
+        if let Some(outcome) = self.verify_identity().await {
+            return outcome;
+        }
+        self.retry_count += 1;
+        let command = self.intervene(classification.state).await;
+
+        // 重置计数器，继续监控
+        self.stuck_count = 0;
+        TickOutcome::Intervened { command }
+    }
+
+    /// 把卡住时的 pane 内容交给 LLM 分析并记录结果，失败或超时不影响干预
+    async fn analyze_with_llm(&self, content: &str) {
+        let Some(llm) = &self.llm else {
+            return;
+        };
+        match tokio::time::timeout(LLM_TIMEOUT, llm.analyze_state(content)).await {
+            Ok(Ok(analysis)) => println!("[{}] 🤖 LLM 分析: {}", self.label, analysis.trim()),
+            Ok(Err(e)) => eprintln!("[{}] ⚠️ LLM 分析失败: {}", self.label, e),
+            Err(_) => eprintln!("[{}] ⚠️ LLM 分析超时（{} 秒）", self.label, LLM_TIMEOUT.as_secs()),
+        }
+    }
+
+    /// 向 pane 输入前核对身份，不一致或无法确认时返回应当结束本次检查的结果
+    async fn verify_identity(&self) -> Option<TickOutcome> {
+        match self.fingerprint_matches().await {
+            Ok(true) => None,
+            Ok(false) => {
+                eprintln!("[{}] 🛑 pane 已属于其它进程，拒绝发送干预指令", self.label);
+                Some(TickOutcome::PaneReplaced)
+            }
+            Err(e) => {
+                // 无法确认身份时不输入；只有确定是暂时性故障时才继续监控
+                eprintln!("[{}] 🛑 无法核对 pane 指纹，拒绝发送干预指令: {}", self.label, e);
+                Some(match outcome_for_error(&e) {
+                    TickOutcome::CaptureFailed => TickOutcome::PaneReplaced,
+                    outcome => outcome,
+                })
+            }
+        }
+    }
+
+    /// 根据 pane 元数据和屏幕内容判断 agent 是否已退出；刚重启过时等待 grace_sec 后再判断
+    fn detect_exit(&self, metadata: &PaneMetadata, content: &str) -> Option<AgentExit> {
+        let grace = Duration::from_secs(self.config.respawn.grace_sec);
+        if self.respawned_at.is_some_and(|at| at.elapsed() < grace) {
+            return None;
+        }
+        self.exits.detect(metadata, content)
+    }
+
+    /// agent 已退出：配置了重启命令时重新启动，否则停止干预
+    async fn handle_exit(&mut self, exit: AgentExit) -> TickOutcome {
+        eprintln!("[{}] 💀 agent 已退出: {}", self.label, exit.describe());
+        let respawn = self.config.respawn.clone();
+        let Some(command) = respawn.command else {
+            eprintln!("[{}] 🛑 未配置 respawn.command，不再发送干预指令", self.label);
+            return TickOutcome::AgentExited;
+        };
+        if self.respawn_attempts >= respawn.max_attempts {
+            eprintln!("[{}] 🛑 已连续重启 {} 次仍未恢复工作，放弃重启", self.label, self.respawn_attempts);
+            return TickOutcome::AgentExited;
+        }
+
+        // 已死亡的 pane 不会被回收，无需核对；向 shell 输入或替换进程前必须确认还是原来的 pane
+        if exit != AgentExit::PaneDead {
+            if let Some(outcome) = self.verify_identity().await {
+                return outcome;
+            }
+        }
+
+        self.respawn_attempts += 1;
+        let result = if exit == AgentExit::PaneDead || respawn.method == RespawnMethod::RespawnPane {
+            println!("[{}] 🔄 respawn-pane 重启 agent [{}/{}]: '{}'", self.label, self.respawn_attempts, respawn.max_attempts, command);
+            self.backend.respawn_pane(&self.pane, &command).await
+        } else {
+            println!("[{}] 🔄 在 shell 中重启 agent [{}/{}]: '{}'", self.label, self.respawn_attempts, respawn.max_attempts, command);
+            self.type_command(&command).await
+        };
+        if let Err(e) = result {
+            eprintln!("[{}] ❌ 重启 agent 失败: {}", self.label, e);
+            return match outcome_for_error(&e) {
+                TickOutcome::CaptureFailed => TickOutcome::AgentExited,
+                outcome => outcome,
+            };
+        }
+
+        println!("[{}] ✅ 已执行重启命令，{} 秒内不再检测退出", self.label, respawn.grace_sec);
+        self.respawned_at = Some(Instant::now());
+        self.usage.respawned();
+        self.stuck_count = 0;
+        // respawn-pane 会换掉 pane 主进程，重新记录指纹
+        self.record_fingerprint().await;
+        TickOutcome::Respawned { command }
+    }
+
+    /// 在 shell 中输入命令并回车
+    async fn type_command(&self, command: &str) -> Result<()> {
+        self.backend.send_text(&self.pane, command).await?;
+        sleep(Duration::from_millis(self.config.intervention.command_delay_ms)).await;
+        self.backend.send_keys(&self.pane, "Enter").await
+    }
+
+    /// 捕获并分析 pane 内容
+    /// 控制模式下如果自上次捕获以来没有任何输出，则直接复用上次的内容
+    /// 没有输出信号时按 tmux 报告的活动时间判断：时间戳只精确到秒，同一时间戳需要再捕获一次确认之后才复用
+    async fn capture(&mut self, activity: Option<u64>) -> anyhow::Result<ActivityResult> {
+        let current_bytes = self.output_signal.as_ref().filter(|s| s.is_alive()).map(|s| s.total_bytes());
+        if let Some(current) = current_bytes {
+            self.detector.record_output(current);
+        }
+
+        if let (Some(current), Some(captured)) = (current_bytes, self.captured_bytes) {
+            if current == captured {
+                println!("[{}] 💤 无新输出，复用上次捕获内容", self.label);
+                return Ok(self.detector.reanalyze());
+            }
+        }
+
+        let activity = activity.filter(|_| current_bytes.is_none());
+        if let (Some(stamp), Some((captured, true))) = (activity, self.captured_activity) {
+            if stamp == captured {
+                println!("[{}] 💤 活动时间未变化，复用上次捕获内容", self.label);
+                return Ok(self.detector.reanalyze());
+            }
+        }
+
+        let result = self.detector.check_activity(&self.backend, &self.pane).await?;
+        self.captured_bytes = current_bytes;
+        self.captured_activity = activity.map(|stamp| (stamp, self.captured_activity.is_some_and(|(captured, _)| captured == stamp)));
+        Ok(result)
+    }
+
+    /// 按审批规则回答权限确认对话框；同一个请求只处理一次，已处理过时返回 None 按普通空闲处理
+    async fn answer_permission(&mut self, content: &str) -> Option<TickOutcome> {
+        let request = self.approval.extract(content);
+        let description = request.as_ref().map_or_else(|| "无法识别的请求".to_string(), |request| request.to_string());
+        if self.last_permission.as_deref() == Some(description.as_str()) {
+            return None;
+        }
+        self.last_permission = Some(description.clone());
+
+        let decision = request.as_ref().map_or(Decision::Escalate, |request| self.approval.decide(request));
+        let (approved, rule) = match decision {
+            Decision::Allow { rule } => (true, rule),
+            Decision::Deny { rule } => (false, rule),
+            Decision::Escalate => {
+                println!("[{}] 🙋 权限确认：{} 没有匹配的审批规则，等待人工处理", self.label, description);
+                return Some(TickOutcome::Escalated { request: description });
+            }
+        };
+
+        if let Some(outcome) = self.verify_identity().await {
+            return Some(outcome);
+        }
+        let keys = if approved { &self.config.approval.approve_keys } else { &self.config.approval.reject_keys }.clone();
+        println!(
+            "[{}] {} 自动{}{} (规则 {})",
+            self.label,
+            if approved { "🔓" } else { "⛔" },
+            if approved { "批准" } else { "拒绝" },
+            description,
+            rule
+        );
+        for key in &keys {
+            if let Err(e) = self.send_command(key).await {
+                eprintln!("[{}] ❌ 回答权限确认失败: {}", self.label, e);
+                break;
+            }
+        }
+        self.stuck_count = 0;
+        Some(TickOutcome::PermissionAnswered { request: description, approved })
+    }
+
+    /// 记录用量并检查预算；刚超出时按 on_exceeded 处理一次，之后暂停干预直到不再超出（例如过了零点）
+    async fn track_usage(&mut self, content: &str) -> Option<TickOutcome> {
+        if let Some(usage) = self.usage.record(content) {
+            match self.usage.recent_tokens(RECENT_USAGE_WINDOW) {
+                Some(recent) if recent > 0 => println!("[{}] 💰 用量: {}（最近 10 分钟 +{} tokens）", self.label, usage, format_count(recent)),
+                _ => println!("[{}] 💰 用量: {}", self.label, usage),
+            }
+        }
+
+        let Some(reason) = self.usage.exceeded() else {
+            if self.budget_exceeded.take().is_some() {
+                println!("[{}] ✅ 用量回到预算以内，恢复干预", self.label);
+            }
+            return None;
+        };
+        if self.budget_exceeded.is_some() {
+            println!("[{}] 💤 {}，暂停干预", self.label, reason);
+            return Some(TickOutcome::BudgetPaused);
+        }
+
+        println!("[{}] 💸 {}", self.label, reason);
+        self.budget_exceeded = Some(reason.clone());
+        let commands = match self.config.usage.on_exceeded {
+            BudgetAction::Pause => Vec::new(),
+            BudgetAction::WrapUp => vec![self.config.usage.wrap_up_prompt.clone()],
+            BudgetAction::Interrupt => self.config.usage.interrupt_keys.clone(),
+        };
+        if !commands.is_empty() {
+            if let Some(outcome) = self.verify_identity().await {
+                return Some(outcome);
+            }
+        }
+        for command in commands {
+            println!("[{}] 🔧 发送预算指令: '{}'", self.label, command);
+            if let Err(e) = self.send_command(&command).await {
+                eprintln!("[{}] ❌ 发送预算指令失败: {}", self.label, e);
+                break;
+            }
+        }
+        self.stuck_count = 0;
+        Some(TickOutcome::BudgetExceeded { reason })
+    }
+
+    /// 检测到循环：依次发送 loop_detection.commands，并清空循环统计
+    async fn break_loop(&mut self, signal: LoopSignal) -> TickOutcome {
+        println!("[{}] 🔁 检测到循环: {}", self.label, signal);
+        if let Some(outcome) = self.verify_identity().await {
+            return outcome;
+        }
+        for command in self.config.loop_detection.commands.clone() {
+            println!("[{}] 🔧 发送循环干预指令: '{}'", self.label, command);
+            if let Err(e) = self.send_command(&command).await {
+                eprintln!("[{}] ❌ 发送循环干预指令失败: {}", self.label, e);
+                break;
+            }
+        }
+        self.loops.reset();
+        self.stuck_count = 0;
+        TickOutcome::LoopDetected { signal }
+    }
+
+    /// 被限流后暂停干预，等待时间取屏幕提示或 default_delay_sec，结束后发送恢复指令
+    /// 不在限流等待中且状态不是 rate_limited 时返回 None
+    async fn back_off(&mut self, classification: &Classification, content: &str) -> Option<TickOutcome> {
+        if let Some(until) = self.suspended_until {
+            let remaining = until.saturating_duration_since(Instant::now());
+            if !remaining.is_zero() {
+                let remaining_sec = remaining.as_secs_f64().ceil() as u64;
+                println!("[{}] ⏳ 等待限流解除，还剩 {} 秒", self.label, remaining_sec);
+                return Some(TickOutcome::Suspended { remaining_sec });
+            }
+            self.suspended_until = None;
+            if let Some(outcome) = self.verify_identity().await {
+                return Some(outcome);
+            }
+            let command = self.config.rate_limit.resume_prompt.clone();
+            println!("[{}] ▶️  限流等待结束，发送恢复指令: '{}'", self.label, command);
+            if let Err(e) = self.send_command(&command).await {
+                eprintln!("[{}] ❌ 发送恢复指令失败: {}", self.label, e);
             }
+            self.stuck_count = 0;
+            self.resumed_hint = (classification.state == AgentState::RateLimited).then(|| rate_limit_hint(classification, content));
+            return Some(TickOutcome::Resumed { command });
         }
+
+        if classification.state != AgentState::RateLimited {
+            self.resumed_hint = None;
+            return None;
+        }
+        // 恢复指令发出后屏幕上仍是原来那条限流提示，不是新的限流，按一般的卡住流程处理
+        if self.resumed_hint.as_ref() == Some(&rate_limit_hint(classification, content)) {
+            return None;
+        }
+        let rate_limit = &self.config.rate_limit;
+        let (delay, source) = match self.retry.retry_delay(content) {
+            Some(delay) => (delay, "屏幕提示"),
+            None => (Duration::from_secs(rate_limit.default_delay_sec), "默认"),
+        };
+        let delay = delay + Duration::from_secs(rate_limit.margin_sec);
+        let delay_sec = delay.as_secs_f64().ceil() as u64;
+        println!(
+            "[{}] 🚦 {} ({})，暂停干预 {} 秒（{}等待时间 + {} 秒余量）",
+            self.label,
+            classification.state,
+            classification.evidence.as_deref().unwrap_or_default(),
+            delay_sec,
+            source,
+            rate_limit.margin_sec
+        );
+        self.suspended_until = Some(Instant::now() + delay);
+        self.stuck_count = 0;
+        Some(TickOutcome::BackedOff { delay_sec })
+    }
+
+    /// 按干预指令的格式发送一条输入，文本在 command_delay_ms 后补发回车
+    async fn send_command(&self, command: &str) -> Result<()> {
+        let intervention = Intervention::parse(command);
+        intervention.deliver(&self.backend, &self.pane).await?;
+        if intervention.needs_enter() {
+            sleep(Duration::from_millis(self.config.intervention.command_delay_ms)).await;
+            self.backend.send_keys(&self.pane, "Enter").await?;
+        }
+        Ok(())
+    }
+
+    /// 该状态下的下一条指令，返回 (指令, 在列表中的序号, 列表长度)
+    /// on_state 中的列表从第一条开始循环，其余状态共用 commands 的循环位置
+    fn next_command(&mut self, state: AgentState) -> (String, usize, usize) {
+        let commands = self.config.commands_for(state).unwrap_or_default().to_vec();
+        if !self.config.intervention.on_state.contains_key(&state) || commands.is_empty() {
+            let (command, new_index) = self.config.get_next_intervention_command(self.command_index);
+            self.command_index = new_index;
+            return (command, new_index + 1, self.config.intervention.commands.len());
+        }
+        let index = self.state_command_index.entry(state).or_insert(0);
+        let position = *index % commands.len();
+        *index = position + 1;
+        (commands[position].clone(), position + 1, commands.len())
+    }
+
+    /// 发送下一个干预指令，返回发送的指令
+    async fn intervene(&mut self, state: AgentState) -> String {
+        // 获取下一个干预指令
+        let (command, position, total) = self.next_command(state);
+
+        println!("[{}] 🔧 尝试干预指令 [{}/{}]: '{}'", self.label, position, total, command);
+
+        // 按键名按按键发送，普通文本按字面输入，多行文本通过粘贴发送
+        let intervention = Intervention::parse(&command);
+        if let Err(e) = intervention.deliver(&self.backend, &self.pane).await {
+            eprintln!("[{}] ❌ 发送指令失败: {}", self.label, e);
+            return command;
+        }
+        println!("[{}] ✅ 已发送指令: {:?}", self.label, intervention);
+
+        // 等待指令输入完成，然后发送回车
+        let command_delay = Duration::from_millis(self.config.intervention.command_delay_ms);
+        let enter_delay = Duration::from_millis(self.config.intervention.enter_delay_ms);
+
+        if !intervention.needs_enter() {
+            println!("[{}] ✅ 按键或斜杠命令已发送，无需回车", self.label);
+        } else {
+            println!("[{}] ⏳ 等待 {}ms 后发送回车...", self.label, self.config.intervention.enter_delay_ms);
+            sleep(command_delay).await;
+
+            if let Err(e) = self.backend.send_keys(&self.pane, "Enter").await {
+                eprintln!("[{}] ❌ 发送回车失败: {}", self.label, e);
+            } else {
+                sleep(enter_delay.saturating_sub(command_delay)).await;
+                println!("[{}] ✅ 已发送回车，指令执行完成", self.label);
+            }
+        }
+
+        command
+    }
+}
+
+/// 一个待监控的目标：名称、该目标的完整配置、后端以及可选的控制模式输出信号和 tmux hook 事件
+pub struct MonitorTarget<B: PaneBackend> {
+    pub name: String,
+    pub config: Config,
+    pub backend: B,
+    pub output_signal: Option<Arc<OutputSignal>>,
+    pub hook_events: Option<Arc<HookEvents>>,
+}
+
+/// 等待下次检查（或控制模式下的下一次输出）
+async fn wait_for_trigger(output_signal: Option<&OutputSignal>, interval: Duration, debounce: Duration) -> TickTrigger {
+    match output_signal {
+        Some(signal) if signal.wait(interval, debounce).await => TickTrigger::Output,
+        Some(_) => TickTrigger::Interval,
+        None => {
+            sleep(interval).await;
+            TickTrigger::Interval
+        }
+    }
+}
+
+/// 监控主循环：按配置的间隔反复检查，pane 消失时退出
+/// 提供控制模式输出信号时，pane 有输出会立即触发检查；安装了 tmux hook 时，静默和退出事件会立即推送过来
+pub async fn run_monitoring_loop<B: PaneBackend>(target: MonitorTarget<B>) -> Result<()> {
+    let MonitorTarget { name, mut config, backend, output_signal, hook_events } = target;
+    let interval = Duration::from_secs(config.monitoring.interval);
+    let debounce = Duration::from_millis(config.monitoring.control_debounce_ms);
+
+    // 解析为稳定的 pane 标识，之后的捕获和输入都使用它
+    // tmux 服务器暂时不可用时等待它恢复，而不是直接放弃目标
+    let mut failures = 0;
+    loop {
+        match backend.resolve_pane(&config.tmux.pane).await {
+            Ok(pane) => {
+                if pane != config.tmux.pane {
+                    println!("[{}] 🎯 目标 {} 解析为 pane {}", name, config.tmux.pane, pane);
+                }
+                config.tmux.pane = pane;
+                break;
+            }
+            Err(e) => match outcome_for_error(&e) {
+                TickOutcome::BackendUnavailable => {
+                    failures += 1;
+                    let delay = backoff_delay(interval, failures);
+                    eprintln!("[{}] ⏳ {}，{} 秒后重试", name, e, delay.as_secs());
+                    sleep(delay).await;
+                }
+                TickOutcome::BackendFatal { error } => return Err(anyhow::anyhow!(error)),
+                _ => {
+                    eprintln!("[{}] ❌ {}", name, e);
+                    return Ok(());
+                }
+            },
+        }
+    }
+
+    let mut session = MonitorSession::new(backend, config).with_label(&name);
+    if let Some(signal) = &output_signal {
+        session = session.with_output_signal(signal.clone());
+    }
+    session.record_agent().await;
+    session.record_fingerprint().await;
+
+    let mut trigger = TickTrigger::Interval;
+    failures = 0;
+    loop {
+        match session.tick(trigger).await {
+            TickOutcome::PaneMissing | TickOutcome::PaneReplaced | TickOutcome::AgentExited => break,
+            TickOutcome::BackendFatal { error } => return Err(anyhow::anyhow!(error)),
+            TickOutcome::BackendUnavailable => {
+                // 服务器重启或网络抖动时不退出，也不把等待计入卡住次数
+                failures += 1;
+                let delay = backoff_delay(interval, failures);
+                println!("[{}] ⏳ 后端暂时不可用（连续 {} 次），{} 秒后重试", name, failures, delay.as_secs());
+                sleep(delay).await;
+                trigger = TickTrigger::Interval;
+                continue;
+            }
+            _ => failures = 0,
+        }
+
+        let wait = wait_for_trigger(output_signal.as_deref(), interval, debounce);
+        trigger = match &hook_events {
+            Some(events) => tokio::select! {
+                trigger = wait => trigger,
+                event = events.next() => match event {
+                    HookEvent::Died => {
+                        println!("[{}] 💀 tmux 报告 pane 中的程序已退出", name);
+                        if !session.respawn_enabled() {
+                            break;
+                        }
+                        // 由下一次检查发现 pane_dead 并重启
+                        TickTrigger::Interval
+                    }
+                    HookEvent::Silence => {
+                        println!("[{}] 🔕 tmux 报告 pane 已静默 {} 秒", name, session.config.monitoring.stuck_sec);
+                        TickTrigger::Silence
+                    }
+                    HookEvent::Activity | HookEvent::Bell => TickTrigger::Output,
+                },
+            },
+            None => wait.await,
+        };
     }
-    
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// 为每个目标启动独立的 tokio 任务并等待全部结束
+/// 各任务拥有独立的会话状态（卡住计数、指令索引），一个目标退出或崩溃不影响其它目标
+pub async fn supervise<B>(targets: Vec<MonitorTarget<B>>) -> Result<()>
+where
+    B: PaneBackend + Send + Sync + 'static,
+{
+    let mut tasks = JoinSet::new();
+    for target in targets {
+        tasks.spawn(async move {
+            let name = target.name.clone();
+            (name, run_monitoring_loop(target).await)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((name, Ok(()))) => println!("[{}] 监控结束", name),
+            Ok((name, Err(e))) => eprintln!("[{}] ❌ 监控异常退出: {}", name, e),
+            Err(e) => eprintln!("❌ 监控任务崩溃: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::scripted::ScriptedBackend;
+    use crate::config::ProfileKind;
+
+    fn test_config() -> Config {
+        let mut config = Config::default();
+        config.apply_profile();
+        config.intervention.commands = vec!["cmd1".to_string(), "/cmd2".to_string()];
+        config.intervention.command_delay_ms = 0;
+        config.intervention.enter_delay_ms = 0;
+        config
+    }
+
+    #[tokio::test]
+    async fn test_active_resets_stuck_count() {
+        let backend = ScriptedBackend::new(["idle", "idle", "working...", "idle"]);
+        let mut session = MonitorSession::new(backend, test_config());
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 2 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Active);
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        assert!(session.backend.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_intervention_after_threshold() {
+        let backend = ScriptedBackend::new(["idle"]);
+        let mut session = MonitorSession::new(backend, test_config());
+
+        session.tick(TickTrigger::Interval).await;
+        session.tick(TickTrigger::Interval).await;
+        let outcome = session.tick(TickTrigger::Interval).await;
+
+        // get_next_intervention_command 从索引0的下一个开始
+        assert_eq!(outcome, TickOutcome::Intervened { command: "/cmd2".to_string() });
+        // 斜杠命令不发送回车
+        assert_eq!(session.backend.sent(), vec![("%18".to_string(), Intervention::Text("/cmd2".to_string()))]);
+
+        session.tick(TickTrigger::Interval).await;
+        session.tick(TickTrigger::Interval).await;
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Intervened { command: "cmd1".to_string() });
+        let sent = session.backend.sent();
+        assert_eq!(sent[1].1, Intervention::Text("cmd1".to_string()));
+        assert_eq!(sent[2].1, Intervention::Key("Enter".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_stops_after_max_retry() {
+        // 连续卡住 3 轮，然后恢复工作，再卡住 1 轮
+        let mut frames = vec!["idle"; 3 * STUCK_THRESHOLD];
+        frames.push("working");
+        frames.extend(vec!["idle"; STUCK_THRESHOLD]);
+        let backend = ScriptedBackend::new(frames);
+        let mut config = test_config();
+        config.monitoring.max_retry = 2;
+        let mut session = MonitorSession::new(backend, config);
+        let texts = |session: &MonitorSession<ScriptedBackend>| {
+            session.backend.sent().into_iter().filter(|(_, sent)| matches!(sent, Intervention::Text(_))).count()
+        };
+
+        let mut outcomes = Vec::new();
+        for _ in 0..3 * STUCK_THRESHOLD {
+            outcomes.push(session.tick(TickTrigger::Interval).await);
+        }
+        assert!(matches!(outcomes[STUCK_THRESHOLD - 1], TickOutcome::Intervened { .. }));
+        assert!(matches!(outcomes[2 * STUCK_THRESHOLD - 1], TickOutcome::Intervened { .. }));
+        assert_eq!(outcomes[3 * STUCK_THRESHOLD - 1], TickOutcome::RetriesExhausted);
+        assert_eq!(texts(&session), 2);
+
+        // agent 恢复工作后重新计数
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Active);
+        for _ in 0..STUCK_THRESHOLD {
+            session.tick(TickTrigger::Interval).await;
+        }
+        assert_eq!(texts(&session), 3);
+    }
+
+    #[test]
+    fn test_llm_prompt_follows_profile() {
+        assert!(MonitorSession::new(ScriptedBackend::new(["idle"]), test_config()).llm.is_none());
+        let mut config = Config { profile: Some(ProfileKind::Claude), ..Config::default() };
+        config.llm.backend = "ollama".to_string();
+        config.apply_profile();
+        let session = MonitorSession::new(ScriptedBackend::new(["idle"]), config);
+        let prompt = session.llm.as_ref().unwrap().prompt_for("> ");
+        assert!(prompt.starts_with("分析以下tmux pane内容，判断Claude Code是否处于卡住状态"));
+        assert!(prompt.ends_with("\n\n> "));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_state_is_reported_once_and_capped() {
+        let backend = ScriptedBackend::new(["compiling..."; 8].into_iter().chain(["aider> "]));
+        let mut config = test_config();
+        config.idle_prompts = Some(vec![r"^[\w-]*> ?$".to_string()]);
+        let mut session = MonitorSession::new(backend, config);
+
+        for tick in 1..=8 {
+            let outcome = session.tick(TickTrigger::Interval).await;
+            assert_eq!(outcome, TickOutcome::Idle { stuck_count: tick.min(STUCK_THRESHOLD) });
+            assert_eq!(session.unknown_warned, tick >= STUCK_THRESHOLD);
+            assert!(session.stuck_count < STUCK_THRESHOLD);
+        }
+        assert!(session.backend.sent().is_empty());
+        // 看到提示符后照常干预，并清除提示标记
+        assert!(matches!(session.tick(TickTrigger::Interval).await, TickOutcome::Intervened { .. }));
+        assert!(!session.unknown_warned);
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_idle_prompt() {
+        let backend = ScriptedBackend::new(["running tests...", "running tests...", "running tests...", "aider> "]);
+        let mut config = test_config();
+        config.idle_prompts = Some(vec![r"^[\w-]*> ?$".to_string()]);
+        let mut session = MonitorSession::new(backend, config);
+
+        session.tick(TickTrigger::Interval).await;
+        session.tick(TickTrigger::Interval).await;
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 3 });
+        assert!(session.backend.sent().is_empty());
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Intervened { command: "/cmd2".to_string() });
+    }
+
+    #[tokio::test]
+    async fn test_intervention_chosen_by_state() {
+        let permission = "Bash(rm -rf build)\nDo you want to proceed?\n❯ 1. Yes\n  2. No";
+        let full = "Error: prompt is too long: 210000 tokens > 200000 maximum\n> ";
+        let backend = ScriptedBackend::new([permission, permission, permission, full, full, full]);
+        let mut config = test_config();
+        config.intervention.on_state.insert(AgentState::ContextFull, vec!["/compact".to_string()]);
+        let mut session = MonitorSession::new(backend, config);
+
+        // 等待权限确认时默认不输入任何内容
+        session.tick(TickTrigger::Interval).await;
+        session.tick(TickTrigger::Interval).await;
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Held { state: AgentState::AwaitingPermission });
+        assert!(session.backend.sent().is_empty());
+
+        // 上下文已满时使用该状态的指令，而不是循环 commands
+        session.tick(TickTrigger::Interval).await;
+        session.tick(TickTrigger::Interval).await;
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Intervened { command: "/compact".to_string() });
+    }
+
+    #[tokio::test]
+    async fn test_permission_answered_by_policy() {
+        let dialog = |command: &str| format!("Bash({})\nDo you want to proceed?\n❯ 1. Yes\n  2. No", command);
+        let backend = ScriptedBackend::new([dialog("cargo test"), dialog("cargo test"), "working".to_string(), dialog("git push"), dialog("rm -rf /")]);
+        let mut config = test_config();
+        config.approval.enabled = true;
+        config.approval.allow_commands = vec!["cargo *".to_string()];
+        config.approval.deny_commands = vec!["rm *".to_string()];
+        let mut session = MonitorSession::new(backend, config);
+
+        let approved = TickOutcome::PermissionAnswered { request: "命令 `cargo test`".to_string(), approved: true };
+        assert_eq!(session.tick(TickTrigger::Interval).await, approved);
+        assert_eq!(session.backend.sent(), vec![("%18".to_string(), Intervention::Key("Enter".to_string()))]);
+        // 对话框还在时不重复回答
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Active);
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Escalated { request: "命令 `git push`".to_string() });
+        assert_eq!(
+            session.tick(TickTrigger::Interval).await,
+            TickOutcome::PermissionAnswered { request: "命令 `rm -rf /`".to_string(), approved: false }
+        );
+        assert_eq!(session.backend.sent().last().unwrap().1, Intervention::Key("Escape".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_backs_off_then_resumes() {
+        let limited = "API Error: 429 rate limit exceeded, retry in 0s";
+        let backend = ScriptedBackend::new([limited, limited, "working", "Error: rate limit exceeded, retry in 42s", "> "]);
+        let mut config = test_config();
+        config.rate_limit.margin_sec = 0;
+        let mut session = MonitorSession::new(backend, config);
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::BackedOff { delay_sec: 0 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Resumed { command: "继续".to_string() });
+        assert_eq!(
+            session.backend.sent(),
+            vec![("%18".to_string(), Intervention::Text("继续".to_string())), ("%18".to_string(), Intervention::Key("Enter".to_string()))]
+        );
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Active);
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::BackedOff { delay_sec: 42 });
+        // 等待期间即使回到输入提示符也不干预
+        assert!(matches!(session.tick(TickTrigger::Silence).await, TickOutcome::Suspended { remaining_sec: 41 | 42 }));
+        assert_eq!(session.backend.sent().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_rate_limit_hint_resumes_once() {
+        let limited = "API Error: 429 rate limit exceeded, retry in 0s";
+        let again = "API Error: 429 rate limit exceeded, retry in 0s\n> 继续\nAPI Error: 429 rate limit exceeded, retry in 0s";
+        let backend = ScriptedBackend::new([limited, limited, limited, limited, again]);
+        let mut config = test_config();
+        config.rate_limit.margin_sec = 0;
+        let mut session = MonitorSession::new(backend, config);
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::BackedOff { delay_sec: 0 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Resumed { command: "继续".to_string() });
+        // 屏幕没有变化，仍是同一条限流提示
+        assert!(matches!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { .. }));
+        assert!(matches!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { .. }));
+        let resumes = |session: &MonitorSession<ScriptedBackend>| {
+            session.backend.sent().into_iter().filter(|(_, sent)| *sent == Intervention::Text("继续".to_string())).count()
+        };
+        assert_eq!(resumes(&session), 1);
+        // 再次输出同样的提示是新的限流
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::BackedOff { delay_sec: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_loop_interrupted_while_working() {
+        let backend = ScriptedBackend::new(["edit a\nworking", "test b\nworking", "edit a\nworking", "test b\nworking", "edit a\nworking"]);
+        let mut config = test_config();
+        config.loop_detection.enabled = true;
+        config.loop_detection.screen_repeats = 3;
+        config.loop_detection.commands = vec!["Escape".to_string(), "换个思路".to_string()];
+        let mut session = MonitorSession::new(backend, config);
+
+        for _ in 0..4 {
+            assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Active);
+        }
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::LoopDetected { signal: LoopSignal::RepeatedScreen { repeats: 3 } });
+        let sent: Vec<Intervention> = session.backend.sent().into_iter().map(|(_, intervention)| intervention).collect();
+        assert_eq!(
+            sent,
+            vec![Intervention::Key("Escape".to_string()), Intervention::Text("换个思路".to_string()), Intervention::Key("Enter".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_budget_wrap_up_then_pause() {
+        let backend = ScriptedBackend::new(["idle 900 tokens", "idle 1,200 tokens", "idle", "idle", "idle"]);
+        let mut config = test_config();
+        config.usage.budget.session_tokens = Some(1000);
+        config.usage.on_exceeded = BudgetAction::WrapUp;
+        config.usage.wrap_up_prompt = "收尾".to_string();
+        let mut session = MonitorSession::new(backend, config);
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::BudgetExceeded { reason: "本次会话 tokens 1.2k 达到预算 1.0k".to_string() });
+        // 之后即使卡住也不再干预
+        for _ in 0..3 {
+            assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::BudgetPaused);
+        }
+        let sent: Vec<Intervention> = session.backend.sent().into_iter().map(|(_, intervention)| intervention).collect();
+        assert_eq!(sent, vec![Intervention::Text("收尾".to_string()), Intervention::Key("Enter".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_multiline_intervention_is_pasted() {
+        let backend = ScriptedBackend::new(["idle"]);
+        let mut config = test_config();
+        config.intervention.commands = vec!["继续\n先运行测试\n".to_string()];
+        let mut session = MonitorSession::new(backend, config);
+
+        session.tick(TickTrigger::Interval).await;
+        session.tick(TickTrigger::Interval).await;
+        session.tick(TickTrigger::Interval).await;
+
+        let sent: Vec<Intervention> = session.backend.sent().into_iter().map(|(_, sent)| sent).collect();
+        assert_eq!(
+            sent,
+            vec![Intervention::Paste("继续\n先运行测试".to_string()), Intervention::Key("Enter".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refuses_to_type_into_replaced_pane() {
+        let mut backend = ScriptedBackend::new(["idle"]);
+        backend.metadata.pid = Some(std::process::id());
+        let mut session = MonitorSession::new(backend, test_config());
+        session.record_fingerprint().await;
+
+        session.tick(TickTrigger::Interval).await;
+        session.tick(TickTrigger::Interval).await;
+        // pane 标识被回收，主进程换成了其它程序
+        session.backend.metadata.pid = Some(u32::MAX);
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::PaneReplaced);
+        assert!(session.backend.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_silence_alert_goes_straight_to_intervention() {
+        let backend = ScriptedBackend::new(["idle"]);
+        let mut session = MonitorSession::new(backend, test_config());
+
+        assert_eq!(session.tick(TickTrigger::Silence).await, TickOutcome::Intervened { command: "/cmd2".to_string() });
+    }
+
+    #[tokio::test]
+    async fn test_died_hook_stops_loop() {
+        let backend = ScriptedBackend::new(["idle"]);
+        let mut config = test_config();
+        config.monitoring.interval = 3600;
+        let events = Arc::new(HookEvents::default());
+        events.push(HookEvent::Died);
+        let target = MonitorTarget { name: "t".to_string(), config, backend, output_signal: None, hook_events: Some(events) };
+
+        tokio::time::timeout(Duration::from_secs(5), run_monitoring_loop(target)).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_agent_exit_stops_intervention_without_respawn() {
+        let mut backend = ScriptedBackend::new(["opencode", "dev@box:~/app$"]);
+        backend.metadata.current_command = "opencode".to_string();
+        let mut session = MonitorSession::new(backend, test_config());
+        session.record_agent().await;
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        session.backend.metadata.current_command = "bash".to_string();
+        assert_eq!(session.tick(TickTrigger::Silence).await, TickOutcome::AgentExited);
+        assert!(session.backend.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_respawn_typed_into_shell() {
+        let mut backend = ScriptedBackend::new(["working", "dev@box:~/app$"]);
+        backend.metadata.current_command = "opencode".to_string();
+        let mut config = test_config();
+        config.respawn.command = Some("opencode --continue".to_string());
+        let mut session = MonitorSession::new(backend, config);
+        session.record_agent().await;
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Active);
+        session.backend.metadata.current_command = "bash".to_string();
+        assert_eq!(
+            session.tick(TickTrigger::Interval).await,
+            TickOutcome::Respawned { command: "opencode --continue".to_string() }
+        );
+        assert_eq!(
+            session.backend.sent(),
+            vec![
+                ("%18".to_string(), Intervention::Text("opencode --continue".to_string())),
+                ("%18".to_string(), Intervention::Key("Enter".to_string())),
+            ]
+        );
+
+        // 等待 agent 启动期间不会重复重启
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        assert_eq!(session.backend.sent().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_dead_pane_respawned_until_attempts_exhausted() {
+        let mut backend = ScriptedBackend::new(["working"]);
+        backend.metadata.dead = true;
+        let mut config = test_config();
+        config.respawn.command = Some("claude --resume".to_string());
+        config.respawn.max_attempts = 2;
+        config.respawn.grace_sec = 0;
+        let mut session = MonitorSession::new(backend, config);
+
+        let respawned = TickOutcome::Respawned { command: "claude --resume".to_string() };
+        assert_eq!(session.tick(TickTrigger::Interval).await, respawned);
+        assert_eq!(session.tick(TickTrigger::Interval).await, respawned);
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::AgentExited);
+        assert_eq!(session.backend.respawned(), vec!["claude --resume".to_string(); 2]);
+        assert!(session.backend.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_activity_skips_capture() {
+        let mut backend = ScriptedBackend::new(["working", "idle", "idle", "working"]);
+        backend.metadata.activity = Some(100);
+        let mut session = MonitorSession::new(backend, test_config());
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Active);
+        // 同一秒内可能还有输出，再捕获一次确认
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        // 时间戳已确认未变化，复用上次内容，不消耗预设画面
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 2 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Intervened { command: "/cmd2".to_string() });
+
+        session.backend.metadata.activity = Some(101);
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Active);
+    }
+
+    #[tokio::test]
+    async fn test_output_trigger_does_not_count_as_stuck() {
+        let backend = ScriptedBackend::new(["idle"]);
+        let mut session = MonitorSession::new(backend, test_config());
+
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 1 });
+        assert_eq!(session.tick(TickTrigger::Output).await, TickOutcome::Idle { stuck_count: 1 });
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::Idle { stuck_count: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_supervise_isolates_targets() {
+        let mut config_a = test_config();
+        config_a.tmux.pane = "%1".to_string();
+        config_a.monitoring.interval = 0;
+        let mut config_b = test_config();
+        config_b.tmux.pane = "%2".to_string();
+
+        // 目标 b 的 pane 不存在，应立即结束而不影响目标 a
+        let backend_b = Arc::new(ScriptedBackend::new(["idle"]));
+        backend_b.set_exists(false);
+        let backend_a = Arc::new(ScriptedBackend::new(["idle"]));
+
+        let targets = vec![
+            MonitorTarget { name: "b".to_string(), config: config_b, backend: backend_b, output_signal: None, hook_events: None },
+            MonitorTarget { name: "a".to_string(), config: config_a, backend: backend_a.clone(), output_signal: None, hook_events: None },
+        ];
+        let handle = tokio::spawn(supervise(targets));
+
+        // 等待目标 a 完成一次干预
+        for _ in 0..200 {
+            if !backend_a.sent().is_empty() {
+                break;
+            }
+            sleep(Duration::from_millis(5)).await;
+        }
+        backend_a.set_exists(false);
+        handle.await.unwrap().unwrap();
+
+        assert!(backend_a.sent().iter().all(|(pane, _)| pane == "%1"));
+        assert!(!backend_a.sent().is_empty());
+    }
+
+    #[test]
+    fn test_outcome_for_backend_errors() {
+        let outcome = |error: TmuxError| outcome_for_error(&anyhow::Error::from(error));
+        assert_eq!(outcome(TmuxError::PaneGone("can't find pane: %3".to_string())), TickOutcome::PaneMissing);
+        assert_eq!(outcome(TmuxError::ServerNotRunning("no server running".to_string())), TickOutcome::BackendUnavailable);
+        assert_eq!(
+            outcome(TmuxError::TimedOut { command: "capture-pane".to_string(), timeout: Duration::from_secs(5) }),
+            TickOutcome::BackendUnavailable
+        );
+        assert!(matches!(outcome(TmuxError::NotInstalled), TickOutcome::BackendFatal { .. }));
+        assert!(matches!(outcome(TmuxError::PermissionDenied("denied".to_string())), TickOutcome::BackendFatal { .. }));
+        assert_eq!(outcome_for_error(&anyhow::anyhow!("其它错误")), TickOutcome::CaptureFailed);
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let interval = Duration::from_secs(5);
+        assert_eq!(backoff_delay(interval, 1), Duration::from_secs(5));
+        assert_eq!(backoff_delay(interval, 3), Duration::from_secs(20));
+        assert_eq!(backoff_delay(interval, 10), MAX_BACKOFF);
+        assert_eq!(backoff_delay(Duration::ZERO, 1), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_missing_pane() {
+        let backend = ScriptedBackend::new(["working"]);
+        backend.set_exists(false);
+        let mut session = MonitorSession::new(backend, test_config());
+        assert_eq!(session.tick(TickTrigger::Interval).await, TickOutcome::PaneMissing);
+    }
+}
@@ -0,0 +1,140 @@
+use lazy_static::lazy_static;
+use regex::{Regex, RegexBuilder};
+use crate::config::NormalizeConfig;
+
+/// 屏蔽内容的占位符
+const MASK: &str = "#";
+
+/// 常见 agent 使用的 spinner 字符（盲文点阵之外）
+const SPINNER_CHARS: &str = "✻✽✶✳✢✺✹✸✷✦✧◐◓◑◒◴◵◶◷◰◱◲◳◢◣◤◥●○◉◎⏺·•∙";
+
+/// 竖向边框字符
+const VERTICAL_RULE_CHARS: &str = "│┃║";
+
+lazy_static! {
+    // 横向边框线，长度随 pane 宽度变化
+    static ref HORIZONTAL_RULE: Regex = Regex::new(r"[─━═╌╍┄┅┈┉]{2,}").unwrap();
+}
+
+/// 屏幕内容规范化
+/// 依次执行：正则屏蔽、去掉 spinner 字符、合并空白、忽略换行位置，再对结果计算 seahash 指纹
+/// 只用于判断内容是否变化，活动标识仍在原始内容上匹配
+pub struct Normalizer {
+    masks: Vec<Regex>,
+    strip_spinners: bool,
+    fold_whitespace: bool,
+    reflow: bool,
+}
+
+impl Normalizer {
+    /// 无效的屏蔽正则会被忽略并给出警告
+    pub fn new(config: &NormalizeConfig) -> Self {
+        let masks = config
+            .masks
+            .iter()
+            .filter_map(|pattern| match RegexBuilder::new(pattern).multi_line(true).build() {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    eprintln!("⚠️ 忽略无效的屏蔽正则 {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        Normalizer {
+            masks,
+            strip_spinners: config.strip_spinners,
+            fold_whitespace: config.fold_whitespace,
+            reflow: config.reflow,
+        }
+    }
+
+    pub fn normalize(&self, content: &str) -> String {
+        let mut text = content.to_string();
+        for mask in &self.masks {
+            text = mask.replace_all(&text, MASK).into_owned();
+        }
+
+        if self.strip_spinners {
+            text.retain(|ch| !is_spinner(ch));
+        }
+
+        if self.reflow {
+            // 换行位置随宽度变化，单词可能在任意位置折断，因此去掉全部空白和折行处的竖向边框
+            text.retain(|ch| !ch.is_whitespace() && !VERTICAL_RULE_CHARS.contains(ch));
+            return HORIZONTAL_RULE.replace_all(&text, "─").into_owned();
+        }
+
+        if self.fold_whitespace {
+            text = text
+                .lines()
+                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        text
+    }
+
+    /// 规范化内容的 seahash 指纹
+    pub fn fingerprint(&self, content: &str) -> u64 {
+        seahash::hash(self.normalize(content).as_bytes())
+    }
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer::new(&NormalizeConfig::default())
+    }
+}
+
+fn is_spinner(ch: char) -> bool {
+    ('\u{2800}'..='\u{28FF}').contains(&ch) || SPINNER_CHARS.contains(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw() -> NormalizeConfig {
+        NormalizeConfig { masks: Vec::new(), strip_spinners: false, fold_whitespace: false, reflow: false }
+    }
+
+    #[test]
+    fn test_spinner_and_timer_do_not_count_as_change() {
+        let normalizer = Normalizer::default();
+        let a = normalizer.fingerprint("⠋ Thinking… (12s · esc to interrupt)\n> ");
+        let b = normalizer.fingerprint("⠙ Thinking… (13s · esc to interrupt)\n> ");
+        assert_eq!(a, b);
+        assert_eq!(normalizer.fingerprint("updated 09:41:07"), normalizer.fingerprint("updated 09:41:12"));
+        assert_ne!(normalizer.fingerprint("step 1 of 3"), normalizer.fingerprint("step 2 of 3"));
+    }
+
+    #[test]
+    fn test_resize_does_not_count_as_change() {
+        let normalizer = Normalizer::default();
+        let wide = "╭────────────────╮\n│ hello world    │\n╰────────────────╯\n";
+        let narrow = "╭──────────╮\n│ hello wo │\n│ rld      │\n╰──────────╯\n\n\n";
+        assert_eq!(normalizer.fingerprint(wide), normalizer.fingerprint(narrow));
+    }
+
+    #[test]
+    fn test_individual_steps() {
+        let masks = Normalizer::new(&NormalizeConfig { masks: vec![r"\d+ tokens".to_string()], ..raw() });
+        assert_eq!(masks.normalize("used 1200 tokens"), "used #");
+
+        let spinners = Normalizer::new(&NormalizeConfig { strip_spinners: true, ..raw() });
+        assert_eq!(spinners.normalize("⣾ ✻ working"), "  working");
+
+        let fold = Normalizer::new(&NormalizeConfig { fold_whitespace: true, ..raw() });
+        assert_eq!(fold.normalize("a   b  \n\n   c\n"), "a b\nc");
+
+        // 全部关闭时原样比较
+        assert_eq!(Normalizer::new(&raw()).normalize("⠋ 12s\n\n"), "⠋ 12s\n\n");
+    }
+
+    #[test]
+    fn test_invalid_mask_is_ignored() {
+        let normalizer = Normalizer::new(&NormalizeConfig { masks: vec!["(".to_string(), "x".to_string()], ..raw() });
+        assert_eq!(normalizer.masks.len(), 1);
+    }
+}
@@ -0,0 +1,247 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use async_trait::async_trait;
+use crate::ansi::StyledScreen;
+use crate::backend::{PaneBackend, PaneMetadata};
+use crate::control::OutputSignal;
+use crate::identity::PaneFingerprint;
+use crate::tmux::TmuxClient;
+use crate::vt::{self, VtScreen};
+
+/// 未配置 log_dir 时原始日志的存放目录
+const DEFAULT_LOG_DIR: &str = "opencode-watch-logs";
+
+/// 日志文件读到末尾后再次读取前的等待时间
+const TAIL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// pipe-pane 后端
+/// 用 tmux pipe-pane 把目标 pane 的全部原始输出追加到日志文件，后台线程持续读取并喂给内置虚拟终端，
+/// 捕获时直接返回虚拟终端还原的屏幕，不再执行 capture-pane
+/// 其它操作（发送按键、元数据查询等）交给 tmux 后端
+pub struct PipePaneBackend {
+    inner: TmuxClient,
+    pane_id: String,
+    screen: Arc<Mutex<VtScreen>>,
+    signal: Arc<OutputSignal>,
+    stop: Arc<AtomicBool>,
+    log_path: PathBuf,
+}
+
+impl PipePaneBackend {
+    /// 在目标 pane 上开启 pipe-pane
+    /// 虚拟终端先用 capture-pane 的当前画面和光标位置初始化，之后只依赖原始输出
+    pub async fn attach(inner: TmuxClient, target: &str, log_dir: Option<&str>, scrollback: usize) -> Result<Self> {
+        let pane_id = inner.resolve_pane(target).await?;
+        let metadata = inner.pane_metadata(&pane_id).await?;
+
+        let log_dir = Path::new(log_dir.unwrap_or(DEFAULT_LOG_DIR));
+        fs::create_dir_all(log_dir)?;
+        let log_path = log_dir.join(log_file_name(&pane_id, SystemTime::now()));
+        File::create(&log_path)?;
+        let log_str = log_path.to_string_lossy().to_string();
+        // 命令由 tmux 交给 shell 执行，路径放在单引号中
+        if log_str.contains('\'') {
+            return Err(anyhow::anyhow!("日志路径中包含单引号，无法开启 pipe-pane: {}", log_str));
+        }
+
+        // 先开启 pipe-pane 再捕获初始画面，捕获之前写入日志的输出已经反映在画面中，从捕获时的文件末尾开始读取
+        inner.run(&["pipe-pane", "-t", &pane_id, &format!("exec cat >> '{}'", log_str)]).await?;
+        let mut screen = VtScreen::new(metadata.height as usize, metadata.width as usize).with_scrollback(scrollback);
+        if let Err(e) = seed_screen(&inner, &mut screen, &pane_id).await {
+            let _ = inner.run(&["pipe-pane", "-t", &pane_id]).await;
+            return Err(e);
+        }
+        let offset = fs::metadata(&log_path)?.len();
+
+        let screen = Arc::new(Mutex::new(screen));
+        let signal = Arc::new(OutputSignal::connected());
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // 读取线程：日志文件 -> 虚拟终端
+        let mut file = File::open(&log_path)?;
+        std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(offset))?;
+        let reader_screen = screen.clone();
+        let reader_signal = signal.clone();
+        let reader_stop = stop.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            loop {
+                match file.read(&mut buf) {
+                    Ok(0) => {
+                        if reader_stop.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        thread::sleep(TAIL_INTERVAL);
+                    }
+                    Ok(n) => {
+                        if !vt::feed_screen(&reader_screen, &buf[..n]) {
+                            eprintln!("⚠️ 虚拟终端无法解析一段输出，已丢弃");
+                        }
+                        reader_signal.record_output(n);
+                    }
+                    Err(_) => break,
+                }
+            }
+            reader_signal.mark_closed();
+        });
+
+        Ok(PipePaneBackend { inner, pane_id, screen, signal, stop, log_path })
+    }
+
+    /// 输出信号，pane 有输出时立即唤醒监控循环
+    pub fn signal(&self) -> Arc<OutputSignal> {
+        self.signal.clone()
+    }
+
+    /// 虚拟终端当前状态的简短描述：光标位置、光标是否可见、主屏幕还是备用屏幕
+    pub fn describe_screen(&self) -> String {
+        let screen = vt::lock_screen(&self.screen);
+        let (row, col) = screen.cursor();
+        format!(
+            "光标 {}:{}{}，{}",
+            row,
+            col,
+            if screen.cursor_visible() { "" } else { "（隐藏）" },
+            if screen.alternate_screen() { "备用屏幕" } else { "主屏幕" }
+        )
+    }
+
+    /// 原始输出日志路径
+    pub fn log_path(&self) -> &Path {
+        &self.log_path
+    }
+}
+
+impl Drop for PipePaneBackend {
+    fn drop(&mut self) {
+        // 不带命令执行 pipe-pane 会关闭已有的管道
+        let _ = self.inner.command(&["pipe-pane", "-t", &self.pane_id]).output();
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl PaneBackend for PipePaneBackend {
+    async fn capture_pane_content(&self, pane: &str) -> Result<String> {
+        Ok(self.capture_screen(pane).await?.visible_text())
+    }
+
+    async fn capture_screen(&self, pane: &str) -> Result<StyledScreen> {
+        if pane != self.pane_id {
+            return self.inner.capture_screen(pane).await;
+        }
+
+        // pane 尺寸可能被用户调整，输出流中不包含尺寸变化，按 tmux 报告的尺寸同步
+        let metadata = self.inner.pane_metadata(pane).await?;
+        let mut screen = vt::lock_screen(&self.screen);
+        let size = (metadata.height as usize, metadata.width as usize);
+        if size.0 > 0 && size.1 > 0 && screen.size() != size {
+            screen.resize(size.0, size.1);
+        }
+        Ok(screen.styled_screen())
+    }
+
+    async fn send_keys(&self, pane: &str, keys: &str) -> Result<()> {
+        self.inner.send_keys(pane, keys).await
+    }
+
+    async fn send_text(&self, pane: &str, text: &str) -> Result<()> {
+        self.inner.send_text(pane, text).await
+    }
+
+    async fn paste_text(&self, pane: &str, text: &str) -> Result<()> {
+        self.inner.paste_text(pane, text).await
+    }
+
+    async fn check_pane_exists(&self, pane: &str) -> Result<bool> {
+        self.inner.check_pane_exists(pane).await
+    }
+
+    async fn pane_metadata(&self, pane: &str) -> Result<PaneMetadata> {
+        self.inner.pane_metadata(pane).await
+    }
+
+    async fn resolve_pane(&self, target: &str) -> Result<String> {
+        self.inner.resolve_pane(target).await
+    }
+
+    async fn pane_fingerprint(&self, pane: &str) -> Result<Option<PaneFingerprint>> {
+        self.inner.pane_fingerprint(pane).await
+    }
+
+    async fn respawn_pane(&self, pane: &str, command: &str) -> Result<()> {
+        self.inner.respawn_pane(pane, command).await
+    }
+}
+
+/// 用 pane 当前的画面、光标位置和屏幕模式初始化虚拟终端
+async fn seed_screen(tmux: &TmuxClient, screen: &mut VtScreen, pane_id: &str) -> Result<()> {
+    let state = tmux.run(&["display-message", "-p", "-t", pane_id, "#{cursor_y}|:|#{cursor_x}|:|#{alternate_on}"]).await?;
+    let content = tmux.run(&["capture-pane", "-p", "-e", "-t", pane_id]).await?;
+    screen.feed(seed_bytes(&state, &content).as_bytes());
+    Ok(())
+}
+
+/// 生成初始化虚拟终端的字节序列
+/// state 为 `光标行|:|光标列|:|是否备用屏幕`，content 为 capture-pane -e 的输出
+fn seed_bytes(state: &str, content: &str) -> String {
+    let fields: Vec<usize> = state.trim().split("|:|").map(|f| f.parse().unwrap_or(0)).collect();
+    let field = |i: usize| fields.get(i).copied().unwrap_or(0);
+
+    let mut seed = String::new();
+    if field(2) == 1 {
+        // 备用屏幕：主屏幕内容无法取得，留空
+        seed.push_str("\x1b[?1049h");
+    }
+    let lines: Vec<&str> = content.trim_end_matches('\n').split('\n').collect();
+    for (i, line) in lines.iter().enumerate() {
+        seed.push_str(&format!("\x1b[{};1H{}\x1b[0m", i + 1, line));
+    }
+    seed.push_str(&format!("\x1b[{};{}H", field(0) + 1, field(1) + 1));
+    seed
+}
+
+/// 日志文件名：pane 标识加开始记录的时间
+fn log_file_name(pane_id: &str, at: SystemTime) -> String {
+    let secs = at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let id: String = pane_id.chars().map(|ch| if ch.is_ascii_alphanumeric() { ch } else { '_' }).collect();
+    format!("pane{}-{}.log", id.trim_start_matches('_'), secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_file_name() {
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(log_file_name("%12", at), "pane12-1700000000.log");
+    }
+
+    #[test]
+    fn test_seed_restores_screen_and_cursor() {
+        let mut screen = VtScreen::new(3, 20);
+        screen.feed(seed_bytes("1|:|5|:|0\n", "\x1b[1mworking\x1b[0m\n> abc\n\n").as_bytes());
+        assert_eq!(screen.text(), "working\n> abc\n\n");
+        assert_eq!(screen.cursor(), (1, 5));
+        assert!(!screen.alternate_screen());
+
+        // 之后的原始输出接着初始画面继续绘制
+        screen.feed(b"d\r\nok");
+        assert_eq!(screen.text(), "working\n> abcd\nok\n");
+    }
+
+    #[test]
+    fn test_seed_alternate_screen() {
+        let mut screen = VtScreen::new(2, 10);
+        screen.feed(seed_bytes("0|:|0|:|1", "tui\n").as_bytes());
+        assert!(screen.alternate_screen());
+        assert_eq!(screen.text(), "tui\n\n");
+    }
+}
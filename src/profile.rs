@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use regex::Regex;
+use crate::classify::AgentState;
+use crate::config::{Config, IndicatorRule, ProfileKind, RegionRef, ScreenRegion};
+
+/// 内置活动标识只在屏幕最下面的这些行中查找：状态行紧贴输入框，agent 输出的正文中出现同样的词不算工作
+const STATUS_LINES: usize = 8;
+
+/// 查找输入提示符时检查的末尾非空行数，输入框下方通常还有状态栏和快捷键提示
+const PROMPT_SEARCH_LINES: usize = 8;
+
+/// 内置 agent 配置档
+/// 不同 agent 的忙碌标识、输入提示符和支持的斜杠命令各不相同，配置档把它们打包在一起
+/// 配置文件中显式填写的字段优先，留空的字段使用配置档中的值
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentProfile {
+    pub agent_commands: Vec<String>,   // 自动发现时识别的程序名
+    pub indicators: Vec<IndicatorRule>,  // 正在工作时屏幕上出现的标识
+    pub idle_prompts: Vec<String>,     // 等待输入时的提示符正则，为空表示不确认提示符
+    pub commands: Vec<String>,         // 该 agent 上可以安全使用的干预指令
+    pub on_state: HashMap<AgentState, Vec<String>>,  // 特定状态下的指令，例如上下文已满时压缩上下文
+    pub approve_keys: Vec<String>,     // 在权限确认对话框中批准
+    pub reject_keys: Vec<String>,      // 在权限确认对话框中拒绝
+    pub llm_prompt: String,            // 让 LLM 判断状态时的提示语
+}
+
+impl ProfileKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProfileKind::Opencode => "opencode",
+            ProfileKind::Claude => "claude",
+            ProfileKind::Aider => "aider",
+            ProfileKind::Codex => "codex",
+            ProfileKind::GeminiCli => "gemini-cli",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [ProfileKind::Opencode, ProfileKind::Claude, ProfileKind::Aider, ProfileKind::Codex, ProfileKind::GeminiCli]
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+
+    pub fn profile(&self) -> AgentProfile {
+        match self {
+            ProfileKind::Opencode => AgentProfile {
+                agent_commands: strings(&["opencode"]),
+                indicators: vec![bottom(literal("working"), STATUS_LINES), bottom(literal("esc interrupt"), STATUS_LINES)],
+                idle_prompts: Vec::new(),
+                commands: strings(&["继续", "继续工作", "请继续", "/compact", "C-c", "请继续工作"]),
+                on_state: compact("/compact"),
+                approve_keys: strings(&["Enter"]),
+                reject_keys: strings(&["Escape"]),
+                llm_prompt: llm_prompt("opencode"),
+            },
+            // Claude Code 等 Claude 风格的 CLI：C-c 连按两次会退出，改用 Escape 中断
+            ProfileKind::Claude => AgentProfile {
+                agent_commands: strings(&["claude"]),
+                indicators: vec![bottom(ignore_case("esc to interrupt"), STATUS_LINES)],
+                idle_prompts: strings(&[r"^[│\s]*>\s"]),
+                commands: strings(&["继续", "请继续", "继续工作", "/compact", "Escape", "请继续工作"]),
+                on_state: compact("/compact"),
+                approve_keys: strings(&["Enter"]),
+                reject_keys: strings(&["Escape"]),
+                llm_prompt: llm_prompt("Claude Code"),
+            },
+            // aider 没有 /compact；等待模型时显示 Waiting for <模型>
+            ProfileKind::Aider => AgentProfile {
+                agent_commands: strings(&["aider"]),
+                indicators: vec![bottom(literal("Waiting for"), 3)],
+                idle_prompts: strings(&[r"^[\w-]*> ?$"]),
+                commands: strings(&["继续", "请继续", "continue"]),
+                on_state: HashMap::new(),
+                approve_keys: strings(&["y"]),
+                reject_keys: strings(&["n"]),
+                llm_prompt: llm_prompt("aider"),
+            },
+            ProfileKind::Codex => AgentProfile {
+                agent_commands: strings(&["codex"]),
+                indicators: vec![bottom(ignore_case("esc to interrupt"), STATUS_LINES)],
+                idle_prompts: Vec::new(),
+                commands: strings(&["继续", "请继续", "继续工作", "/compact"]),
+                on_state: compact("/compact"),
+                approve_keys: strings(&["Enter"]),
+                reject_keys: strings(&["Escape"]),
+                llm_prompt: llm_prompt("Codex CLI"),
+            },
+            // gemini-cli 压缩上下文的命令是 /compress
+            ProfileKind::GeminiCli => AgentProfile {
+                agent_commands: strings(&["gemini"]),
+                indicators: vec![bottom(ignore_case("esc to cancel"), STATUS_LINES)],
+                idle_prompts: strings(&["Type your message"]),
+                commands: strings(&["继续", "请继续", "继续工作", "/compress"]),
+                on_state: compact("/compress"),
+                approve_keys: strings(&["Enter"]),
+                reject_keys: strings(&["Escape"]),
+                llm_prompt: llm_prompt("Gemini CLI"),
+            },
+        }
+    }
+}
+
+impl Config {
+    /// 当前选择的配置档，未选择时为 opencode
+    pub fn agent_profile(&self) -> AgentProfile {
+        self.profile.unwrap_or_default().profile()
+    }
+
+    /// 命令行 --profile：替换全局配置档，并清除各目标单独指定的配置档
+    pub fn select_profile(&mut self, profile: Option<ProfileKind>) {
+        self.profile = profile;
+        for target in &mut self.targets {
+            target.profile = None;
+        }
+    }
+
+    /// 用配置档补全留空的字段：程序名、活动标识、输入提示符和干预指令
+    pub fn apply_profile(&mut self) {
+        let profile = self.agent_profile();
+        if self.tmux.agent_commands.is_empty() {
+            self.tmux.agent_commands = profile.agent_commands;
+        }
+        if self.indicators.is_empty() {
+            self.indicators = profile.indicators;
+        }
+        if self.idle_prompts.is_none() {
+            self.idle_prompts = Some(profile.idle_prompts);
+        }
+        if self.intervention.commands.is_empty() {
+            self.intervention.commands = profile.commands;
+        }
+        if self.approval.approve_keys.is_empty() {
+            self.approval.approve_keys = profile.approve_keys;
+        }
+        if self.approval.reject_keys.is_empty() {
+            self.approval.reject_keys = profile.reject_keys;
+        }
+        for (state, commands) in profile.on_state {
+            self.intervention.on_state.entry(state).or_insert(commands);
+        }
+    }
+}
+
+/// agent 等待输入时的提示符
+/// 配置了提示符时，只有屏幕末尾出现提示符才发送干预指令，避免把指令打进确认对话框或运行中的子进程
+pub struct IdlePrompts {
+    prompts: Vec<Regex>,
+}
+
+impl IdlePrompts {
+    /// 无效的提示符正则会被忽略并给出警告
+    pub fn new(patterns: &[String]) -> Self {
+        let prompts = patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    eprintln!("⚠️ 忽略无效的输入提示符正则 {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        IdlePrompts { prompts }
+    }
+
+    /// 没有配置提示符时不做确认
+    pub fn is_enabled(&self) -> bool {
+        !self.prompts.is_empty()
+    }
+
+    /// 屏幕末尾是否出现了输入提示符
+    pub fn visible(&self, content: &str) -> bool {
+        content
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .rev()
+            .take(PROMPT_SEARCH_LINES)
+            .any(|line| self.prompts.iter().any(|prompt| prompt.is_match(line)))
+    }
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+fn literal(pattern: &str) -> IndicatorRule {
+    IndicatorRule::literal(pattern)
+}
+
+fn ignore_case(pattern: &str) -> IndicatorRule {
+    IndicatorRule { ignore_case: true, ..IndicatorRule::literal(pattern) }
+}
+
+/// 上下文已满时使用的压缩命令
+fn compact(command: &str) -> HashMap<AgentState, Vec<String>> {
+    HashMap::from([(AgentState::ContextFull, strings(&[command]))])
+}
+
+/// 只在屏幕最下面 n 行中查找
+fn bottom(rule: IndicatorRule, n: usize) -> IndicatorRule {
+    let region = ScreenRegion { last_lines: Some(n), ..ScreenRegion::default() };
+    IndicatorRule { region: Some(RegionRef::Inline(region)), ..rule }
+}
+
+fn llm_prompt(agent: &str) -> String {
+    format!("分析以下tmux pane内容，判断{}是否处于卡住状态：", agent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicators::IndicatorSet;
+
+    #[test]
+    fn test_profile_names_round_trip() {
+        for kind in [ProfileKind::Opencode, ProfileKind::Claude, ProfileKind::Aider, ProfileKind::Codex, ProfileKind::GeminiCli] {
+            assert_eq!(ProfileKind::from_name(kind.name()), Some(kind));
+            let parsed: ProfileKind = serde_yaml::from_str(kind.name()).unwrap();
+            assert_eq!(parsed, kind);
+        }
+        assert_eq!(ProfileKind::from_name("cursor"), None);
+    }
+
+    #[test]
+    fn test_profile_indicators() {
+        let busy = |kind: ProfileKind, screen: &str| IndicatorSet::new(&kind.profile().indicators, &Default::default()).check(screen).is_active();
+        assert!(busy(ProfileKind::Opencode, "⠋ working"));
+        assert!(busy(ProfileKind::Claude, "✻ Thinking… (12s · esc to interrupt)"));
+        assert!(busy(ProfileKind::GeminiCli, "⠏ Reading files (esc to cancel, 3s)"));
+        assert!(busy(ProfileKind::Aider, "src/main.rs\n\nWaiting for claude-sonnet"));
+        assert!(!busy(ProfileKind::Aider, "Waiting for you\nline\nline\nline\n> "));
+        // 正文中较早出现的标识不算工作
+        let transcript = format!("I am working on it\n{}> ", "line\n".repeat(STATUS_LINES));
+        assert!(!busy(ProfileKind::Opencode, &transcript));
+    }
+
+    #[test]
+    fn test_cli_profile_on_default_config() {
+        // 没有配置文件时使用 Config::default()，--profile claude 必须完整替换成 Claude 的标识和指令
+        let mut config = Config::default();
+        config.select_profile(ProfileKind::from_name("claude"));
+        let (_, resolved) = config.resolve_targets().remove(0);
+        let claude = ProfileKind::Claude.profile();
+        assert_eq!(resolved.indicators, claude.indicators);
+        assert_eq!(resolved.tmux.agent_commands, claude.agent_commands);
+        assert_eq!(resolved.intervention.commands, claude.commands);
+        assert_eq!(resolved.idle_prompts, Some(claude.idle_prompts));
+        let busy = IndicatorSet::new(&resolved.indicators, &Default::default());
+        assert!(busy.check("✻ Thinking… (12s · esc to interrupt)").is_active());
+        assert!(!resolved.indicators.iter().any(|rule| rule.pattern == "esc interrupt"));
+    }
+
+    #[test]
+    fn test_slash_commands_only_where_supported() {
+        assert!(ProfileKind::Opencode.profile().commands.contains(&"/compact".to_string()));
+        assert!(!ProfileKind::Aider.profile().commands.iter().any(|c| c.starts_with('/')));
+        assert!(ProfileKind::GeminiCli.profile().commands.contains(&"/compress".to_string()));
+        assert!(!ProfileKind::Claude.profile().commands.contains(&"C-c".to_string()));
+        assert_eq!(ProfileKind::GeminiCli.profile().on_state[&AgentState::ContextFull], vec!["/compress".to_string()]);
+        assert!(ProfileKind::Aider.profile().on_state.is_empty());
+    }
+
+    #[test]
+    fn test_idle_prompts() {
+        let aider = IdlePrompts::new(&ProfileKind::Aider.profile().idle_prompts);
+        assert!(aider.visible("Tokens: 2.1k sent\n\narchitect> \n\n"));
+        assert!(!aider.visible("Allow edits to file? (Y)es/(N)o [Yes]:"));
+
+        let claude = IdlePrompts::new(&ProfileKind::Claude.profile().idle_prompts);
+        assert!(claude.visible("╭──────╮\n│ > 继续  │\n╰──────╯\n  ? for shortcuts"));
+        assert!(!claude.visible("Do you want to proceed?\n❯ 1. Yes\n  2. No"));
+
+        let none = IdlePrompts::new(&ProfileKind::Opencode.profile().idle_prompts);
+        assert!(!none.is_enabled());
+        assert!(!IdlePrompts::new(&["(".to_string()]).is_enabled());
+    }
+
+    #[test]
+    fn test_explicit_settings_win_over_profile() {
+        let yaml = r#"
+profile: claude
+tmux:
+  pane: "%1"
+monitoring:
+  interval: 5
+  stuck_sec: 30
+  max_retry: 6
+intervention:
+  enter_delay_ms: 100
+indicators:
+  - pattern: "Brewing"
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        config.apply_profile();
+        assert_eq!(config.indicators, vec![IndicatorRule::literal("Brewing")]);
+        assert_eq!(config.intervention.commands, ProfileKind::Claude.profile().commands);
+        assert_eq!(config.tmux.agent_commands, vec!["claude".to_string()]);
+        assert_eq!(config.idle_prompts, Some(ProfileKind::Claude.profile().idle_prompts));
+    }
+}
@@ -0,0 +1,269 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use crate::backend::{control_sequence, PaneBackend, PaneMetadata, BRACKETED_PASTE_END, BRACKETED_PASTE_START};
+use crate::control::OutputSignal;
+use crate::vt::{self, VtScreen};
+
+/// 内置 PTY 会话
+/// 在伪终端中启动 agent，读取它的全部输出喂给虚拟屏幕，同时可选地原样转发到用户终端
+pub struct PtySession {
+    master: Mutex<File>,
+    child: Mutex<Child>,
+    screen: Arc<Mutex<VtScreen>>,
+    signal: Arc<OutputSignal>,
+    command: String,
+}
+
+impl PtySession {
+    /// 在新的伪终端中启动命令
+    /// mirror 不为空时，agent 的输出会同时写入其中（通常是用户终端）
+    pub fn spawn(command: &[String], rows: u16, cols: u16, mirror: Option<File>) -> Result<Self> {
+        let program = command.first().ok_or_else(|| anyhow::anyhow!("未指定要运行的命令"))?;
+        let (master, slave) = open_pty(rows, cols)?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(&command[1..])
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        if std::env::var_os("TERM").is_none() {
+            cmd.env("TERM", "xterm-256color");
+        }
+        // 子进程成为新会话的首进程，并把 pty 从端设为控制终端
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = cmd.spawn()?;
+
+        let master = File::from(master);
+        let screen = Arc::new(Mutex::new(VtScreen::new(rows as usize, cols as usize)));
+        let signal = Arc::new(OutputSignal::connected());
+
+        // 输出线程：pty 主端 -> 虚拟屏幕（+ 用户终端）
+        let mut reader = master.try_clone()?;
+        let reader_screen = screen.clone();
+        let reader_signal = signal.clone();
+        let mut mirror = mirror;
+        thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            loop {
+                // 子进程退出后 Linux 上读取主端会返回 EIO
+                let n = match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                if !vt::feed_screen(&reader_screen, &buf[..n]) {
+                    eprintln!("⚠️ 虚拟终端无法解析一段输出，已丢弃");
+                }
+                if let Some(out) = mirror.as_mut() {
+                    let _ = out.write_all(&buf[..n]);
+                    let _ = out.flush();
+                }
+                reader_signal.record_output(n);
+            }
+            reader_signal.mark_closed();
+        });
+
+        Ok(PtySession {
+            master: Mutex::new(master),
+            child: Mutex::new(child),
+            screen,
+            signal,
+            command: program.clone(),
+        })
+    }
+
+    /// 把用户终端的输入转发给 agent
+    pub fn relay_stdin(self: &Arc<Self>) {
+        let session = self.clone();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = match stdin.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                if session.write_input(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// 跟随用户终端尺寸变化调整 pty 和虚拟屏幕的尺寸
+    pub fn follow_terminal_size(self: &Arc<Self>) {
+        let session = self.clone();
+        thread::spawn(move || {
+            let mut last = crossterm::terminal::size().ok();
+            while session.is_running() {
+                thread::sleep(Duration::from_millis(500));
+                let current = crossterm::terminal::size().ok();
+                if current != last {
+                    if let Some((cols, rows)) = current {
+                        let _ = session.resize(rows, cols);
+                    }
+                    last = current;
+                }
+            }
+        });
+    }
+
+    /// 调整伪终端尺寸，子进程会收到 SIGWINCH
+    pub fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        let size = libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 };
+        let master = self.master.lock().unwrap();
+        if unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ as _, &size) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        vt::lock_screen(&self.screen).resize(rows as usize, cols as usize);
+        Ok(())
+    }
+
+    /// 输出信号，agent 有输出时立即唤醒监控循环
+    pub fn signal(&self) -> Arc<OutputSignal> {
+        self.signal.clone()
+    }
+
+    /// 子进程是否仍在运行
+    pub fn is_running(&self) -> bool {
+        matches!(self.child.lock().unwrap().try_wait(), Ok(None))
+    }
+
+    /// 等待子进程退出
+    pub fn wait(&self) -> Result<ExitStatus> {
+        Ok(self.child.lock().unwrap().wait()?)
+    }
+
+    fn write_input(&self, bytes: &[u8]) -> io::Result<()> {
+        let mut master = self.master.lock().unwrap();
+        master.write_all(bytes)?;
+        master.flush()
+    }
+}
+
+#[async_trait]
+impl PaneBackend for PtySession {
+    async fn capture_pane_content(&self, _pane: &str) -> Result<String> {
+        Ok(vt::lock_screen(&self.screen).text())
+    }
+
+    async fn send_keys(&self, _pane: &str, keys: &str) -> Result<()> {
+        let input = control_sequence(keys).unwrap_or(keys);
+        Ok(self.write_input(input.as_bytes())?)
+    }
+
+    async fn send_text(&self, _pane: &str, text: &str) -> Result<()> {
+        Ok(self.write_input(text.as_bytes())?)
+    }
+
+    async fn paste_text(&self, _pane: &str, text: &str) -> Result<()> {
+        // 与终端粘贴一致：换行转换为回车；只有 agent 开启了 bracketed paste 才加粘贴标记
+        let text = text.replace("\r\n", "\r").replace('\n', "\r");
+        let input = if vt::lock_screen(&self.screen).bracketed_paste() {
+            format!("{}{}{}", BRACKETED_PASTE_START, text, BRACKETED_PASTE_END)
+        } else {
+            text
+        };
+        Ok(self.write_input(input.as_bytes())?)
+    }
+
+    async fn check_pane_exists(&self, _pane: &str) -> Result<bool> {
+        Ok(self.is_running())
+    }
+
+    async fn pane_metadata(&self, pane: &str) -> Result<PaneMetadata> {
+        let (rows, cols) = vt::lock_screen(&self.screen).size();
+        let dead = !self.is_running();
+        Ok(PaneMetadata {
+            pane_id: pane.to_string(),
+            current_command: self.command.clone(),
+            title: self.command.clone(),
+            current_path: std::env::current_dir().map(|p| p.display().to_string()).unwrap_or_default(),
+            pid: Some(self.child.lock().unwrap().id()),
+            width: cols as u16,
+            height: rows as u16,
+            dead,
+            activity: None,
+        })
+    }
+}
+
+/// 打开一对伪终端 (主端, 从端)
+fn open_pty(rows: u16, cols: u16) -> Result<(OwnedFd, OwnedFd)> {
+    let mut master = 0;
+    let mut slave = 0;
+    let mut size = libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 };
+
+    let ret = unsafe {
+        libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::addr_of_mut!(size))
+    };
+    if ret != 0 {
+        return Err(anyhow::anyhow!("openpty 失败: {}", io::Error::last_os_error()));
+    }
+
+    Ok(unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) })
+}
+
+/// 把本进程的标准输出和标准错误重定向到日志文件
+/// 返回指向原标准输出（用户终端）的文件，用于转发 agent 的画面
+pub fn redirect_output_to_log(log: &File) -> Result<File> {
+    unsafe {
+        let terminal = libc::dup(libc::STDOUT_FILENO);
+        if terminal == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let terminal = File::from_raw_fd(terminal);
+        if libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO) == -1 || libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO) == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(terminal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spawn_and_capture() {
+        let command = vec!["sh".to_string(), "-c".to_string(), "printf 'working\\r\\n'; read line; echo \"got $line\"".to_string()];
+        let session = PtySession::spawn(&command, 10, 40, None).unwrap();
+
+        let wait_for = |text: &str| {
+            for _ in 0..200 {
+                if vt::lock_screen(&session.screen).text().contains(text) {
+                    return true;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            false
+        };
+
+        assert!(wait_for("working"));
+        assert!(session.capture_pane_content("pty").await.unwrap().contains("working"));
+        session.send_keys("pty", "继续").await.unwrap();
+        session.send_keys("pty", "Enter").await.unwrap();
+        assert!(wait_for("got 继续"));
+
+        assert!(session.wait().unwrap().success());
+        assert!(!session.check_pane_exists("pty").await.unwrap());
+        assert_eq!(session.pane_metadata("pty").await.unwrap().width, 40);
+    }
+}
@@ -0,0 +1,217 @@
+use std::time::Duration;
+use lazy_static::lazy_static;
+use regex::{Regex, RegexBuilder};
+use crate::config::RateLimitConfig;
+use crate::localtime::{self, LocalTime};
+
+lazy_static! {
+    // 时长中的一段：数字加可选单位，没有单位时按秒
+    static ref DURATION_PART: Regex = Regex::new(r"(\d+(?:\.\d+)?)\s*([a-z]*)").unwrap();
+    // 一天中的时刻：17:30、5pm、5:30 pm
+    static ref CLOCK: Regex = Regex::new(r"^(\d{1,2})(?::(\d{2}))?\s*([ap]m)?$").unwrap();
+    // 时刻后面的时区：UTC、GMT+2、UTC-05:00、+0200、(Europe/Berlin)、PST
+    static ref ZONE: Regex = Regex::new(
+        r"^\s*\(?\s*(?:(?i:utc|gmt)\b\s*(?P<offset>[+-]\d{1,2}(?::?\d{2})?)?|(?P<bare>[+-]\d{2}:?\d{2})\b|(?P<name>[A-Za-z]+(?:/[A-Za-z_+-]+)+|[A-Z]{2,5}\b))"
+    ).unwrap();
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 3600;
+
+/// 时刻后面标注的时区
+#[derive(Debug, PartialEq)]
+enum Zone {
+    Local,          // 没有标注，按本地时间
+    Offset(i64),    // 与 UTC 的时差（秒）
+    Unknown,        // 标注了无法换算的时区名
+}
+
+/// 从限流提示中解析建议的等待时间
+pub struct RetryParser {
+    patterns: Vec<Regex>,
+    search_lines: usize,
+    max_delay: Duration,
+}
+
+impl RetryParser {
+    /// 无效的正则或缺少 delay / clock 命名分组的正则会被忽略并给出警告
+    pub fn new(config: &RateLimitConfig, search_lines: usize) -> Self {
+        let patterns = config
+            .retry_patterns
+            .iter()
+            .filter_map(|pattern| match RegexBuilder::new(pattern).case_insensitive(true).build() {
+                Ok(regex) if regex.capture_names().flatten().any(|name| name == "delay" || name == "clock") => Some(regex),
+                Ok(_) => {
+                    eprintln!("⚠️ 忽略等待时间正则 {:?}：缺少 delay 或 clock 命名分组", pattern);
+                    None
+                }
+                Err(e) => {
+                    eprintln!("⚠️ 忽略无效的等待时间正则 {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        RetryParser { patterns, search_lines, max_delay: Duration::from_secs(config.max_delay_sec) }
+    }
+
+    /// 在屏幕末尾 search_lines 个非空行中从下往上查找等待时间，不超过 max_delay_sec
+    /// 重置时刻标注了无法换算的时区（如 Europe/Berlin）时按 max_delay_sec 等待
+    pub fn retry_delay(&self, content: &str) -> Option<Duration> {
+        self.retry_delay_at(content, localtime::now())
+    }
+
+    fn retry_delay_at(&self, content: &str, now: LocalTime) -> Option<Duration> {
+        let lines: Vec<&str> = content.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+        let tail = &lines[lines.len().saturating_sub(self.search_lines)..];
+        tail.iter().rev().find_map(|line| {
+            self.patterns.iter().find_map(|regex| {
+                let captures = regex.captures(line)?;
+                if let Some(delay) = captures.name("delay") {
+                    return parse_duration(delay.as_str());
+                }
+                let clock = captures.name("clock")?;
+                let target = parse_clock(clock.as_str())?;
+                // 换算成本地时刻：先减去标注时区的时差得到 UTC，再加上本地时差
+                let local = match parse_zone(&line[clock.end()..]) {
+                    Zone::Local => target,
+                    Zone::Offset(offset) => target - offset + now.utc_offset,
+                    Zone::Unknown => return Some(self.max_delay),
+                };
+                let delay = (local - now.seconds_of_day as i64).rem_euclid(SECONDS_PER_DAY);
+                Some(Duration::from_secs(delay as u64))
+            })
+        })
+        .map(|delay| delay.min(self.max_delay))
+    }
+}
+
+/// 解析 "42s"、"1m30s"、"2 minutes 30 seconds"、"250ms" 这样的时长，没有单位的数字按秒
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.to_lowercase();
+    let mut total = 0.0;
+    let mut found = false;
+    for captures in DURATION_PART.captures_iter(&text) {
+        let value: f64 = captures[1].parse().ok()?;
+        let unit = match &captures[2] {
+            "" | "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+            "ms" | "millisecond" | "milliseconds" => 0.001,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 3600.0,
+            "d" | "day" | "days" => 86400.0,
+            _ => continue,
+        };
+        total += value * unit;
+        found = true;
+    }
+    found.then(|| Duration::from_secs_f64(total))
+}
+
+/// 解析一天中的时刻，返回从零点起的秒数
+fn parse_clock(text: &str) -> Option<i64> {
+    let text = text.trim().to_lowercase();
+    let captures = CLOCK.captures(&text)?;
+    let mut hour: i64 = captures[1].parse().ok()?;
+    let minute: i64 = captures.get(2).map_or(Some(0), |m| m.as_str().parse().ok())?;
+    match captures.get(3).map(|m| m.as_str()) {
+        Some(_) if hour == 0 || hour > 12 => return None,
+        Some("am") => hour %= 12,
+        Some(_) => hour = hour % 12 + 12,
+        None => {}
+    }
+    (hour < 24 && minute < 60).then_some(hour * 3600 + minute * 60)
+}
+
+/// 解析时刻后面的时区，UTC / GMT 加减时差或 +0200 这样的时差可以换算，其它时区名无法换算
+fn parse_zone(text: &str) -> Zone {
+    let Some(captures) = ZONE.captures(text) else {
+        return Zone::Local;
+    };
+    if captures.name("name").is_some() {
+        let name = &captures["name"];
+        return match name.rsplit('/').next() {
+            Some("UTC" | "GMT") => Zone::Offset(0),
+            _ => Zone::Unknown,
+        };
+    }
+    match captures.name("offset").or(captures.name("bare")) {
+        Some(offset) => parse_offset(offset.as_str()).map_or(Zone::Unknown, Zone::Offset),
+        None => Zone::Offset(0),
+    }
+}
+
+/// 解析 "+2"、"-05:00"、"+0530" 这样的时差，返回秒数
+fn parse_offset(text: &str) -> Option<i64> {
+    let (sign, digits) = text.split_at(1);
+    let sign = if sign == "-" { -1 } else { 1 };
+    let digits = digits.replace(':', "");
+    let (hour, minute) = match digits.len() {
+        1 | 2 => (digits.parse::<i64>().ok()?, 0),
+        3 | 4 => {
+            let (hour, minute) = digits.split_at(digits.len() - 2);
+            (hour.parse::<i64>().ok()?, minute.parse::<i64>().ok()?)
+        }
+        _ => return None,
+    };
+    (hour <= 14 && minute < 60).then_some(sign * (hour * 3600 + minute * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> RetryParser {
+        RetryParser::new(&RateLimitConfig::default(), 15)
+    }
+
+    // 现在是本地时间 10:00，本地时区为 UTC+1
+    const NOW: LocalTime = LocalTime { day: 0, seconds_of_day: 10 * 3600, utc_offset: 3600 };
+
+    fn delay(content: &str) -> Option<u64> {
+        parser().retry_delay_at(content, NOW).map(|delay| delay.as_secs())
+    }
+
+    #[test]
+    fn test_provider_messages() {
+        assert_eq!(delay("Error: rate limit exceeded, retry in 42s"), Some(42));
+        assert_eq!(delay("API Error: 429 rate_limit_error · Retrying in 30 seconds… (attempt 2/10)"), Some(30));
+        assert_eq!(delay("Rate limit reached for gpt-4o. Please try again in 1m30s."), Some(90));
+        assert_eq!(delay("try again after 2 minutes and 5 seconds"), Some(125));
+        assert_eq!(delay("HTTP 503 Service Unavailable\nRetry-After: 120"), Some(120));
+        assert_eq!(delay(r#"  "retryDelay": "27s""#), Some(27));
+        assert_eq!(delay("Please try again in 250ms"), Some(0));
+        assert_eq!(delay("529 overloaded_error"), None);
+    }
+
+    #[test]
+    fn test_reset_clock() {
+        assert_eq!(delay("5-hour limit reached ∙ resets 10:30"), Some(1800));
+        // 已经过去的时刻表示明天，但不超过上限
+        assert_eq!(delay("usage limit reached, resets 9am"), Some(6 * 3600));
+        assert_eq!(parse_clock("12am"), Some(0));
+        assert_eq!(parse_clock("12:15 pm"), Some(12 * 3600 + 900));
+        assert_eq!(parse_clock("13pm"), None);
+    }
+
+    #[test]
+    fn test_reset_clock_with_zone() {
+        let max_delay = RateLimitConfig::default().max_delay_sec;
+        // 本地 10:00 即 UTC 9:00
+        assert_eq!(delay("usage limit reached, resets 2pm UTC"), Some(5 * 3600));
+        assert_eq!(delay("resets at 15:00 (GMT+2)"), Some(4 * 3600));
+        assert_eq!(delay("resets 11:30 +01:00"), Some(5400));
+        assert_eq!(delay("resets 1pm (Etc/UTC)"), Some(4 * 3600));
+        // 无法换算的时区名不能当成本地时间，按上限等待
+        assert_eq!(delay("Your limit will reset at 3pm (Europe/Berlin)."), Some(max_delay));
+        assert_eq!(delay("resets 3pm PST"), Some(max_delay));
+        assert_eq!(parse_zone(". Please wait"), Zone::Local);
+        assert_eq!(parse_zone(" and try again"), Zone::Local);
+        assert_eq!(parse_offset("-0530"), Some(-(5 * 3600 + 1800)));
+    }
+
+    #[test]
+    fn test_latest_hint_wins() {
+        let content = "rate limit exceeded, retry in 10s\n> 继续\nrate limit exceeded, retry in 20s\n";
+        assert_eq!(delay(content), Some(20));
+        let short = RetryParser::new(&RateLimitConfig::default(), 1);
+        assert_eq!(short.retry_delay_at("retry in 10s\n\nrate limit\n", NOW), None);
+    }
+}
@@ -0,0 +1,235 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::process::Command;
+use crate::backend::{control_sequence, PaneBackend, PaneMetadata, BRACKETED_PASTE_END, BRACKETED_PASTE_START};
+
+/// hardcopy 文件序号，保证同一进程内的临时文件不重名
+static HARDCOPY_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// 等待 hardcopy 文件写出的最长时间
+const HARDCOPY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// GNU screen 后端
+/// 目标格式为 `会话[:窗口]`，例如 `agent`、`12345.agent:1`；省略窗口时使用会话当前窗口
+pub struct ScreenClient {
+    scrollback: bool,  // hardcopy 时包含滚动历史（hardcopy -h）
+}
+
+impl ScreenClient {
+    pub fn new() -> Self {
+        ScreenClient { scrollback: false }
+    }
+
+    /// 捕获时包含滚动历史
+    pub fn with_scrollback(mut self, scrollback: bool) -> Self {
+        self.scrollback = scrollback;
+        self
+    }
+
+    /// 对目标会话/窗口执行 screen -X 命令
+    async fn execute(&self, target: &str, command: &[&str]) -> Result<()> {
+        let (session, window) = split_target(target);
+        let mut cmd = Command::new("screen");
+        cmd.args(["-S", session]);
+        if let Some(window) = window {
+            cmd.args(["-p", window]);
+        }
+        let output = cmd.arg("-X").args(command).output().await?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "screen -X {} failed: {}{}",
+                command.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// 对目标会话/窗口执行 screen -Q 查询
+    async fn query(&self, target: &str, command: &str) -> Result<String> {
+        let (session, window) = split_target(target);
+        let mut cmd = Command::new("screen");
+        cmd.args(["-S", session]);
+        if let Some(window) = window {
+            cmd.args(["-p", window]);
+        }
+        let output = cmd.args(["-Q", command]).output().await?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("screen -Q {} failed: {}", command, String::from_utf8_lossy(&output.stdout)));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+#[async_trait]
+impl PaneBackend for ScreenClient {
+    async fn capture_pane_content(&self, pane: &str) -> Result<String> {
+        let path = hardcopy_path();
+        let path_str = path.to_string_lossy().to_string();
+
+        let mut command = vec!["hardcopy"];
+        if self.scrollback {
+            command.push("-h");
+        }
+        command.push(&path_str);
+        self.execute(pane, &command).await?;
+
+        // screen 异步写出 hardcopy 文件，等待文件出现
+        let started = Instant::now();
+        let content = loop {
+            match tokio::fs::read(&path).await {
+                Ok(bytes) => break bytes,
+                Err(_) if started.elapsed() < HARDCOPY_TIMEOUT => tokio::time::sleep(Duration::from_millis(20)).await,
+                Err(e) => return Err(anyhow::anyhow!("读取 screen hardcopy 失败: {}", e)),
+            }
+        };
+        let _ = tokio::fs::remove_file(&path).await;
+
+        Ok(trim_trailing_blank_lines(&String::from_utf8_lossy(&content)))
+    }
+
+    async fn send_keys(&self, pane: &str, keys: &str) -> Result<()> {
+        let text = key_to_stuff(keys);
+        self.execute(pane, &["stuff", &text]).await
+    }
+
+    async fn send_text(&self, pane: &str, text: &str) -> Result<()> {
+        self.execute(pane, &["stuff", &escape_stuff(text)]).await
+    }
+
+    async fn paste_text(&self, pane: &str, text: &str) -> Result<()> {
+        // screen 不知道目标程序是否开启了 bracketed paste，直接加上粘贴标记
+        let bracketed = format!("{}{}{}", BRACKETED_PASTE_START, text, BRACKETED_PASTE_END);
+        self.execute(pane, &["stuff", &escape_stuff(&bracketed)]).await
+    }
+
+    async fn check_pane_exists(&self, pane: &str) -> Result<bool> {
+        let (session, _) = split_target(pane);
+        // 没有任何会话时 screen -ls 的退出码也不为 0，因此只看输出
+        let output = Command::new("screen").arg("-ls").output().await?;
+        Ok(list_sessions(&String::from_utf8_lossy(&output.stdout)).iter().any(|name| session_matches(name, session)))
+    }
+
+    async fn pane_metadata(&self, pane: &str) -> Result<PaneMetadata> {
+        let (session, _) = split_target(pane);
+        let title = self.query(pane, "title").await.unwrap_or_default();
+        let pid = session.split('.').next().and_then(|pid| pid.parse().ok());
+
+        Ok(PaneMetadata {
+            pane_id: pane.to_string(),
+            current_command: title.clone(),
+            title,
+            pid,
+            ..PaneMetadata::default()
+        })
+    }
+}
+
+/// 拆分 `会话[:窗口]`
+fn split_target(target: &str) -> (&str, Option<&str>) {
+    match target.rsplit_once(':') {
+        Some((session, window)) if !window.is_empty() => (session, Some(window)),
+        _ => (target, None),
+    }
+}
+
+/// 生成 hardcopy 临时文件路径
+fn hardcopy_path() -> PathBuf {
+    let seq = HARDCOPY_SEQ.fetch_add(1, Ordering::SeqCst);
+    std::env::temp_dir().join(format!("opencode-watch-hardcopy-{}-{}.txt", std::process::id(), seq))
+}
+
+/// 把干预指令转换为 stuff 的参数
+/// 常用按键名转换为对应的控制字符；文本中的 screen 特殊字符需要转义
+fn key_to_stuff(keys: &str) -> String {
+    match control_sequence(keys) {
+        Some(sequence) => sequence.to_string(),
+        None => escape_stuff(keys),
+    }
+}
+
+/// 转义 stuff 参数中 screen 会解释的字符
+fn escape_stuff(text: &str) -> String {
+    text.chars()
+        .flat_map(|ch| match ch {
+            '\\' | '^' | '$' => vec!['\\', ch],
+            _ => vec![ch],
+        })
+        .collect()
+}
+
+/// 解析 screen -ls 的输出，返回会话名列表（pid.name）
+fn list_sessions(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| line.starts_with('\t'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// 会话名匹配：完整的 pid.name、单独的 name 或单独的 pid
+fn session_matches(full_name: &str, wanted: &str) -> bool {
+    if full_name == wanted {
+        return true;
+    }
+    match full_name.split_once('.') {
+        Some((pid, name)) => name == wanted || pid == wanted,
+        None => false,
+    }
+}
+
+/// 去掉 hardcopy 末尾用空行填充的部分
+fn trim_trailing_blank_lines(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(|line| line.trim_end()).collect();
+    let end = lines.iter().rposition(|line| !line.is_empty()).map_or(0, |i| i + 1);
+    lines[..end].iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_target() {
+        assert_eq!(split_target("agent"), ("agent", None));
+        assert_eq!(split_target("123.agent:2"), ("123.agent", Some("2")));
+        assert_eq!(split_target("agent:"), ("agent:", None));
+    }
+
+    #[test]
+    fn test_key_to_stuff() {
+        assert_eq!(key_to_stuff("Enter"), "\r");
+        assert_eq!(key_to_stuff("C-c"), "\x03");
+        assert_eq!(key_to_stuff("继续"), "继续");
+        assert_eq!(key_to_stuff("echo $HOME ^C \\n"), "echo \\$HOME \\^C \\\\n");
+    }
+
+    #[test]
+    fn test_list_sessions() {
+        let output = "There are screens on:\n\t4242.agent\t(Detached)\n\t77.other\t(11/02/2025 10:00:00 AM)\t(Attached)\n2 Sockets in /run/screen/S-me.\n";
+        assert_eq!(list_sessions(output), vec!["4242.agent".to_string(), "77.other".to_string()]);
+    }
+
+    #[test]
+    fn test_session_matches() {
+        assert!(session_matches("4242.agent", "agent"));
+        assert!(session_matches("4242.agent", "4242"));
+        assert!(session_matches("4242.agent", "4242.agent"));
+        assert!(!session_matches("4242.agent", "agen"));
+    }
+
+    #[test]
+    fn test_trim_trailing_blank_lines() {
+        assert_eq!(trim_trailing_blank_lines("a  \nworking\n\n   \n\n"), "a\nworking\n");
+        assert_eq!(trim_trailing_blank_lines("\n\n"), "");
+    }
+}
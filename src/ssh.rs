@@ -0,0 +1,127 @@
+use std::process::Command;
+use crate::config::RemoteConfig;
+
+/// 通过 SSH 在远程主机上执行命令
+/// 使用 OpenSSH 连接复用（ControlMaster），首次执行时建立主连接，之后的每次轮询都复用它，不再重新握手
+#[derive(Debug, Clone)]
+pub struct SshTransport {
+    config: RemoteConfig,
+}
+
+impl SshTransport {
+    pub fn new(config: RemoteConfig) -> Self {
+        SshTransport { config }
+    }
+
+    /// 日志中显示的目标主机，例如 `dev@buildbox:2222`
+    pub fn destination(&self) -> String {
+        let host = match &self.config.user {
+            Some(user) => format!("{}@{}", user, self.config.host),
+            None => self.config.host.clone(),
+        };
+        match self.config.port {
+            Some(port) => format!("{}:{}", host, port),
+            None => host,
+        }
+    }
+
+    /// 构造在远程主机上执行 program args 的 ssh 命令
+    pub fn command(&self, program: &str, args: &[&str]) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.args(self.ssh_args()).arg("--").arg(remote_command(program, args));
+        cmd
+    }
+
+    /// ssh 自身的参数：连接复用、认证和目标主机
+    fn ssh_args(&self) -> Vec<String> {
+        let control_path = std::env::temp_dir().join("opencode-watch-ssh-%C");
+        let mut args = vec![
+            "-o".to_string(),
+            "ControlMaster=auto".to_string(),
+            "-o".to_string(),
+            format!("ControlPath={}", control_path.display()),
+            "-o".to_string(),
+            format!("ControlPersist={}", self.config.control_persist_sec),
+            // 不能弹出密码或主机确认提示，否则监控循环会卡住
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+        ];
+        if let Some(port) = self.config.port {
+            args.push("-p".to_string());
+            args.push(port.to_string());
+        }
+        if let Some(identity) = &self.config.identity_file {
+            args.push("-i".to_string());
+            args.push(identity.clone());
+        }
+        if let Some(user) = &self.config.user {
+            args.push("-l".to_string());
+            args.push(user.clone());
+        }
+        args.push(self.config.host.clone());
+        args
+    }
+}
+
+/// ssh 把参数拼接后交给远程 shell 执行，每个参数都需要单独加引号
+fn remote_command(program: &str, args: &[&str]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().copied())
+        .map(quote_arg)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 用单引号包住参数，参数中的单引号写作 '\''
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|ch| ch.is_ascii_alphanumeric() || "%@:./_-=+,".contains(ch)) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(user: Option<&str>, port: Option<u16>) -> SshTransport {
+        SshTransport::new(RemoteConfig {
+            host: "buildbox".to_string(),
+            user: user.map(|u| u.to_string()),
+            port,
+            identity_file: Some("~/.ssh/id_watch".to_string()),
+            tmux_socket: None,
+            control_persist_sec: 600,
+        })
+    }
+
+    #[test]
+    fn test_quote_arg() {
+        assert_eq!(quote_arg("%18"), "%18");
+        assert_eq!(quote_arg("work:1.0"), "work:1.0");
+        assert_eq!(quote_arg("#{pane_id}|:|#{pane_pid}"), "'#{pane_id}|:|#{pane_pid}'");
+        assert_eq!(quote_arg("it's 继续"), "'it'\\''s 继续'");
+        assert_eq!(quote_arg(""), "''");
+    }
+
+    #[test]
+    fn test_remote_command() {
+        assert_eq!(remote_command("tmux", &["send-keys", "-l", "-t", "%3", "--", "echo $HOME"]), "tmux send-keys -l -t %3 -- 'echo $HOME'");
+    }
+
+    #[test]
+    fn test_ssh_args_reuse_connection() {
+        let transport = remote(Some("dev"), Some(2222));
+        let args = transport.ssh_args();
+        assert!(args.contains(&"ControlMaster=auto".to_string()));
+        assert!(args.contains(&"ControlPersist=600".to_string()));
+        assert!(args.iter().any(|arg| arg.starts_with("ControlPath=") && arg.ends_with("opencode-watch-ssh-%C")));
+        assert!(args.windows(2).any(|pair| pair == ["-p", "2222"]));
+        assert!(args.windows(2).any(|pair| pair == ["-i", "~/.ssh/id_watch"]));
+        assert!(args.windows(2).any(|pair| pair == ["-l", "dev"]));
+        assert_eq!(args.last().unwrap(), "buildbox");
+
+        assert_eq!(transport.destination(), "dev@buildbox:2222");
+        assert_eq!(remote(None, None).destination(), "buildbox");
+    }
+}
@@ -1,45 +1,510 @@
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use anyhow::Result;
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+use crate::ansi::{parse_ansi, StyledScreen};
+use crate::backend::{PaneBackend, PaneMetadata};
+use crate::config::{CaptureConfig, RemoteConfig, TmuxConfig};
+use crate::discovery::DiscoveredPane;
+use crate::identity::{self, PaneFingerprint};
+use crate::ssh::SshTransport;
 
-pub struct TmuxClient;
+/// 格式输出的字段分隔符
+/// tmux 3.3 起会把格式输出中的制表符等控制字符替换为下划线，因此使用可打印的分隔符
+const FIELD_SEPARATOR: &str = "|:|";
+
+/// pane 元数据查询格式，字段以 FIELD_SEPARATOR 分隔
+const METADATA_FORMAT: &str = "#{pane_id}|:|#{pane_current_command}|:|#{pane_title}|:|#{pane_current_path}|:|#{pane_pid}|:|#{pane_width}|:|#{pane_height}|:|#{pane_dead}|:|#{pane_activity}|:|#{window_activity}";
+
+/// 单次 tmux 命令的默认超时
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// tmux 命令失败的原因
+/// 监控循环按类型分别处理：pane 消失时结束监控，服务器未运行或超时时稍后重试，未安装或无权限时直接退出
+#[derive(Debug, thiserror::Error)]
+pub enum TmuxError {
+    #[error("找不到 tmux 可执行文件，请确认已安装 tmux（远程目标还需要 ssh）")]
+    NotInstalled,
+    #[error("tmux 服务器未运行: {0}")]
+    ServerNotRunning(String),
+    #[error("pane 已不存在: {0}")]
+    PaneGone(String),
+    #[error("没有权限访问 tmux 服务器: {0}")]
+    PermissionDenied(String),
+    #[error("tmux {command} 超过 {timeout:?} 未返回")]
+    TimedOut { command: String, timeout: Duration },
+    #[error("tmux {command} failed: {stderr}")]
+    Failed { command: String, stderr: String },
+    #[error("执行 tmux 失败: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl TmuxError {
+    /// 根据退出码和错误输出判断失败原因
+    fn from_output(command: &str, code: Option<i32>, stderr: &str) -> Self {
+        let message = stderr.trim().to_string();
+        let lower = message.to_lowercase();
+        if code == Some(127) || lower.contains("command not found") {
+            // 经 SSH 执行时，远程 shell 找不到 tmux 的退出码为 127
+            TmuxError::NotInstalled
+        } else if lower.contains("permission denied") || lower.contains("access not allowed") {
+            TmuxError::PermissionDenied(message)
+        } else if lower.contains("no server running") || lower.contains("error connecting to") || lower.contains("server exited") {
+            TmuxError::ServerNotRunning(message)
+        } else if lower.contains("can't find pane") || lower.contains("can't find window") || lower.contains("can't find session") {
+            TmuxError::PaneGone(message)
+        } else {
+            TmuxError::Failed { command: command.to_string(), stderr: message }
+        }
+    }
+}
+
+/// 解析 METADATA_FORMAT 对应的字段
+fn parse_metadata(fields: &[&str]) -> Option<PaneMetadata> {
+    if fields.len() < 8 {
+        return None;
+    }
+
+    Some(PaneMetadata {
+        pane_id: fields[0].to_string(),
+        current_command: fields[1].to_string(),
+        title: fields[2].to_string(),
+        current_path: fields[3].to_string(),
+        pid: fields[4].parse().ok(),
+        width: fields[5].parse().unwrap_or(0),
+        height: fields[6].parse().unwrap_or(0),
+        dead: fields[7] == "1",
+        // tmux 3.4 之前没有 pane_activity，退回到所在窗口的活动时间
+        activity: fields.get(8).and_then(|f| f.parse().ok()).or_else(|| fields.get(9).and_then(|f| f.parse().ok())),
+    })
+}
+
+/// 同一 tmux 服务器上多个目标共用的 pane 列表
+/// 一次 list-panes -a 取回全部 pane 的元数据、尺寸和活动时间，max_age 内的重复查询直接使用上次的结果
+pub struct PaneListCache {
+    max_age: Duration,
+    snapshot: tokio::sync::Mutex<Option<(Instant, Vec<DiscoveredPane>)>>,
+}
+
+impl PaneListCache {
+    pub fn new(max_age: Duration) -> Self {
+        PaneListCache { max_age, snapshot: tokio::sync::Mutex::new(None) }
+    }
+}
+
+/// 要连接的 tmux 服务器
+#[derive(Debug, Clone, PartialEq)]
+pub enum TmuxSocket {
+    Name(String),  // tmux -L：tmux socket 目录下的服务器名称
+    Path(String),  // tmux -S：socket 完整路径，例如挂载进容器的宿主机 socket
+}
+
+impl TmuxSocket {
+    /// 按配置选择服务器，未配置时为 None（默认服务器）
+    /// socket_path 优先于 socket_name，与同时给出 -S 和 -L 时 tmux 自身的行为一致；remote.tmux_socket 视为远程主机上的 socket_path
+    pub fn from_config(config: &TmuxConfig) -> Option<Self> {
+        let remote_socket = config.remote.as_ref().and_then(|remote| remote.tmux_socket.clone());
+        if let Some(path) = config.socket_path.clone().or(remote_socket) {
+            return Some(TmuxSocket::Path(path));
+        }
+        config.socket_name.clone().map(TmuxSocket::Name)
+    }
+
+    fn args(&self) -> [&str; 2] {
+        match self {
+            TmuxSocket::Name(name) => ["-L", name],
+            TmuxSocket::Path(path) => ["-S", path],
+        }
+    }
+}
+
+impl std::fmt::Display for TmuxSocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [flag, value] = self.args();
+        write!(f, "{} {}", flag, value)
+    }
+}
+
+#[derive(Clone)]
+pub struct TmuxClient {
+    capture: CaptureConfig,
+    ssh: Option<SshTransport>,  // 远程主机上的 tmux，None 表示本机
+    socket: Option<TmuxSocket>, // 非默认的 tmux 服务器
+    timeout: Duration,          // 单次命令超时
+    pane_cache: Option<Arc<PaneListCache>>,  // 与其它目标共用的 pane 列表
+}
 
 impl TmuxClient {
     pub fn new() -> Self {
-        TmuxClient
-    }
-    
-    pub fn capture_pane_content(&self, pane: &str) -> Result<String> {
-        let output = Command::new("tmux")
-            .args(["capture-pane", "-p", "-t", pane])
-            .output()?;
-        
-        if !output.status.success() {
-            return Err(anyhow::anyhow!("tmux capture-pane failed: {}", String::from_utf8_lossy(&output.stderr)));
-        }
-        
-        Ok(String::from_utf8(output.stdout)?)
-    }
-    
-    pub fn send_keys(&self, pane: &str, keys: &str) -> Result<()> {
-        let output = Command::new("tmux")
-            .args(["send-keys", "-t", pane, keys])
-            .output()?;
-        
-        if !output.status.success() {
-            return Err(anyhow::anyhow!("tmux send-keys failed: {}", String::from_utf8_lossy(&output.stderr)));
-        }
-        
-        Ok(())
+        TmuxClient {
+            capture: CaptureConfig::default(),
+            ssh: None,
+            socket: None,
+            timeout: DEFAULT_TIMEOUT,
+            pane_cache: None,
+        }
+    }
+
+    /// 设置捕获选项（滚动历史、转义序列、合并换行）
+    pub fn with_capture(mut self, capture: CaptureConfig) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// 通过 SSH 操作远程主机上的 tmux，None 表示本机
+    pub fn with_remote(mut self, remote: Option<RemoteConfig>) -> Self {
+        self.ssh = remote.map(SshTransport::new);
+        self
+    }
+
+    /// 连接指定的 tmux 服务器，None 表示默认服务器；远程时指远程主机上的服务器
+    pub fn with_socket(mut self, socket: Option<TmuxSocket>) -> Self {
+        self.socket = socket;
+        self
     }
-    
-    pub fn check_pane_exists(&self, pane: &str) -> bool {
-        Command::new("tmux")
-            .args(["list-panes", "-a"])
-            .output()
-            .map(|output| {
-                let output_str = String::from_utf8_lossy(&output.stdout);
-                output_str.contains(pane)
+
+    /// 设置单次命令超时
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 共用 pane 列表：存在检查、元数据查询都从同一次 list-panes -a 的结果中读取
+    pub fn with_pane_cache(mut self, cache: Arc<PaneListCache>) -> Self {
+        self.pane_cache = Some(cache);
+        self
+    }
+
+    /// 标识所连接的 tmux 服务器（主机和 socket），同一服务器上的目标可以共用 pane 列表
+    pub fn server_key(&self) -> String {
+        let host = self.remote_destination().unwrap_or_else(|| "local".to_string());
+        let socket = self.socket.as_ref().map(|socket| socket.to_string()).unwrap_or_else(|| "default".to_string());
+        format!("{} {}", host, socket)
+    }
+
+    /// 远程主机，本机 tmux 时为 None
+    pub fn remote_destination(&self) -> Option<String> {
+        self.ssh.as_ref().map(|ssh| ssh.destination())
+    }
+
+    /// 构造 tmux 命令，远程时经 SSH 在目标主机上执行
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut tmux_args = Vec::new();
+        if let Some(socket) = &self.socket {
+            tmux_args.extend(socket.args());
+        }
+        tmux_args.extend(args);
+        match &self.ssh {
+            Some(ssh) => ssh.command("tmux", &tmux_args),
+            None => {
+                let mut cmd = Command::new("tmux");
+                cmd.args(tmux_args);
+                cmd
+            }
+        }
+    }
+
+    /// 执行 tmux 命令并返回标准输出
+    pub async fn run(&self, args: &[&str]) -> Result<String, TmuxError> {
+        execute(self.command(args), args[0], None, self.timeout).await
+    }
+
+    /// 执行 tmux 命令，把 input 写入其标准输入
+    async fn run_with_input(&self, args: &[&str], input: &[u8]) -> Result<String, TmuxError> {
+        execute(self.command(args), args[0], Some(input), self.timeout).await
+    }
+
+    /// 列出所有会话中的全部 pane，共用 pane 列表时可能是 max_age 内的缓存结果
+    pub async fn list_panes(&self) -> Result<Vec<DiscoveredPane>, TmuxError> {
+        let Some(cache) = &self.pane_cache else {
+            return self.query_panes().await;
+        };
+        // 查询期间持有锁，同时到期的多个目标只执行一次 list-panes
+        let mut snapshot = cache.snapshot.lock().await;
+        if let Some((at, panes)) = snapshot.as_ref() {
+            if at.elapsed() < cache.max_age {
+                return Ok(panes.clone());
+            }
+        }
+        let panes = self.query_panes().await?;
+        *snapshot = Some((Instant::now(), panes.clone()));
+        Ok(panes)
+    }
+
+    /// 执行 list-panes -a
+    async fn query_panes(&self) -> Result<Vec<DiscoveredPane>, TmuxError> {
+        let format = format!("#{{session_name}}:#{{window_index}}.#{{pane_index}}{}{}", FIELD_SEPARATOR, METADATA_FORMAT);
+        let text = self.run(&["list-panes", "-a", "-F", &format]).await?;
+
+        Ok(text
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split(FIELD_SEPARATOR).collect();
+                let (target, rest) = fields.split_first()?;
+                Some(DiscoveredPane {
+                    target: target.to_string(),
+                    metadata: parse_metadata(rest)?,
+                })
             })
-            .unwrap_or(false)
+            .collect())
+    }
+
+    /// 按 pane 标识、位置或标题精确查找 pane
+    pub async fn find_pane(&self, target: &str) -> Result<Option<DiscoveredPane>> {
+        let panes = self.list_panes().await?;
+        Ok(identity::find_pane(&panes, target)?.cloned())
+    }
+
+    /// 构造 capture-pane 参数
+    fn capture_args(&self, pane: &str) -> Vec<String> {
+        let mut args = vec!["capture-pane".to_string(), "-p".to_string()];
+        if self.capture.escapes {
+            args.push("-e".to_string());
+        }
+        if self.capture.join_lines {
+            args.push("-J".to_string());
+        }
+        if self.capture.scrollback > 0 {
+            args.push("-S".to_string());
+            args.push(format!("-{}", self.capture.scrollback));
+        }
+        args.push("-t".to_string());
+        args.push(pane.to_string());
+        args
+    }
+}
+
+/// 在超时限制内执行命令
+/// 超时后丢弃的子进程会被杀掉，不会在后台残留
+async fn execute(command: Command, name: &str, input: Option<&[u8]>, timeout: Duration) -> Result<String, TmuxError> {
+    let mut command = tokio::process::Command::from(command);
+    command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = command.spawn().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => TmuxError::NotInstalled,
+        _ => TmuxError::Io(e),
+    })?;
+
+    let run = async {
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin.write_all(input).await?;
+        }
+        child.wait_with_output().await
+    };
+    let output = match tokio::time::timeout(timeout, run).await {
+        Ok(output) => output?,
+        Err(_) => return Err(TmuxError::TimedOut { command: name.to_string(), timeout }),
+    };
+
+    if !output.status.success() {
+        return Err(TmuxError::from_output(name, output.status.code(), &String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[async_trait]
+impl PaneBackend for TmuxClient {
+    async fn capture_pane_content(&self, pane: &str) -> Result<String> {
+        Ok(self.capture_screen(pane).await?.visible_text())
+    }
+
+    async fn capture_screen(&self, pane: &str) -> Result<StyledScreen> {
+        let args = self.capture_args(pane);
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+        let text = self.run(&args).await?;
+
+        let mut screen = if self.capture.escapes {
+            parse_ansi(&text)
+        } else {
+            StyledScreen::from_plain(&text)
+        };
+
+        // 带滚动历史时，最后 pane_height 行才是当前可见屏幕
+        if self.capture.scrollback > 0 {
+            let height = self.pane_metadata(pane).await?.height as usize;
+            screen.visible_start = screen.lines.len().saturating_sub(height);
+        }
+
+        Ok(screen)
+    }
+
+    async fn send_keys(&self, pane: &str, keys: &str) -> Result<()> {
+        self.run(&["send-keys", "-t", pane, keys]).await?;
+        Ok(())
+    }
+
+    async fn send_text(&self, pane: &str, text: &str) -> Result<()> {
+        // -l 按字面输入，避免 "Enter"、"C-c" 之类的文本被解释成按键
+        self.run(&["send-keys", "-l", "-t", pane, "--", text]).await?;
+        Ok(())
+    }
+
+    async fn paste_text(&self, pane: &str, text: &str) -> Result<()> {
+        let buffer = format!("opencode-watch-{}", std::process::id());
+
+        // 通过标准输入写入命名缓冲区，文本内容不经过命令行解析
+        self.run_with_input(&["load-buffer", "-b", &buffer, "-"], text.as_bytes()).await?;
+
+        // -p：目标程序开启了 bracketed paste 时加上粘贴标记；-d：粘贴后删除缓冲区
+        self.run(&["paste-buffer", "-p", "-d", "-b", &buffer, "-t", pane]).await?;
+        Ok(())
+    }
+
+    async fn check_pane_exists(&self, pane: &str) -> Result<bool> {
+        Ok(self.find_pane(pane).await?.is_some())
+    }
+
+    async fn pane_metadata(&self, pane: &str) -> Result<PaneMetadata> {
+        if self.pane_cache.is_some() {
+            if let Some(found) = self.list_panes().await?.into_iter().find(|found| found.metadata.pane_id == pane) {
+                return Ok(found.metadata);
+            }
+        }
+        let line = self.run(&["display-message", "-p", "-t", pane, METADATA_FORMAT]).await?;
+        let fields: Vec<&str> = line.trim_end_matches('\n').split(FIELD_SEPARATOR).collect();
+        parse_metadata(&fields).ok_or_else(|| anyhow::anyhow!("tmux display-message 输出格式异常: {}", line))
+    }
+
+    async fn resolve_pane(&self, target: &str) -> Result<String> {
+        match self.find_pane(target).await? {
+            Some(pane) => Ok(pane.metadata.pane_id),
+            None => Err(anyhow::anyhow!("找不到 pane {}（支持 %N、session:window.pane、title:标题）", target)),
+        }
+    }
+
+    async fn pane_fingerprint(&self, pane: &str) -> Result<Option<PaneFingerprint>> {
+        let Some(pid) = self.pane_metadata(pane).await?.pid else {
+            return Ok(None);
+        };
+        match &self.ssh {
+            // 远程 pane 的 pid 属于远程主机，启动时间也要从远程主机的 /proc 读取
+            Some(ssh) => {
+                let path = format!("/proc/{}/stat", pid);
+                let start_time = execute(ssh.command("cat", &[&path]), "cat", None, self.timeout)
+                    .await
+                    .ok()
+                    .and_then(|stat| identity::parse_stat_start_time(&stat));
+                Ok(Some(PaneFingerprint { pid, start_time }))
+            }
+            None => Ok(Some(PaneFingerprint::of_pid(pid))),
+        }
+    }
+
+    async fn respawn_pane(&self, pane: &str, command: &str) -> Result<()> {
+        // -k：程序仍在运行时先结束它；已退出（remain-on-exit）的 pane 直接重新启动
+        self.run(&["respawn-pane", "-k", "-t", pane, command]).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_classify_errors() {
+        let classify = |code, stderr| TmuxError::from_output("list-panes", code, stderr);
+        assert!(matches!(classify(Some(1), "no server running on /tmp/tmux-1000/default\n"), TmuxError::ServerNotRunning(_)));
+        assert!(matches!(
+            classify(Some(1), "error connecting to /tmp/tmux-1000/default (No such file or directory)"),
+            TmuxError::ServerNotRunning(_)
+        ));
+        assert!(matches!(
+            classify(Some(1), "error connecting to /tmp/tmux-0/default (Permission denied)"),
+            TmuxError::PermissionDenied(_)
+        ));
+        assert!(matches!(classify(Some(1), "can't find pane: %99"), TmuxError::PaneGone(_)));
+        assert!(matches!(classify(Some(127), "bash: tmux: command not found"), TmuxError::NotInstalled));
+        assert!(matches!(classify(Some(1), "unknown option -- z"), TmuxError::Failed { .. }));
+    }
+
+    #[test]
+    fn test_socket_from_config() {
+        let mut config = Config::default().tmux;
+        assert_eq!(TmuxSocket::from_config(&config), None);
+
+        config.socket_name = Some("agents".to_string());
+        assert_eq!(TmuxSocket::from_config(&config), Some(TmuxSocket::Name("agents".to_string())));
+
+        config.remote = Some(RemoteConfig {
+            host: "buildbox".to_string(),
+            user: None,
+            port: None,
+            identity_file: None,
+            tmux_socket: Some("/tmp/tmux-1000/remote".to_string()),
+            control_persist_sec: 600,
+        });
+        assert_eq!(TmuxSocket::from_config(&config), Some(TmuxSocket::Path("/tmp/tmux-1000/remote".to_string())));
+
+        config.socket_path = Some("/host/tmux.sock".to_string());
+        assert_eq!(TmuxSocket::from_config(&config), Some(TmuxSocket::Path("/host/tmux.sock".to_string())));
+    }
+
+    #[test]
+    fn test_command_selects_server() {
+        let args = |client: &TmuxClient| {
+            let command = client.command(&["list-panes", "-a"]);
+            command.get_args().map(|arg| arg.to_string_lossy().to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(args(&TmuxClient::new()), ["list-panes", "-a"]);
+        let named = TmuxClient::new().with_socket(Some(TmuxSocket::Name("agents".to_string())));
+        assert_eq!(args(&named), ["-L", "agents", "list-panes", "-a"]);
+
+        // 远程时 socket 参数跟随 tmux 一起在远程主机上执行
+        let remote = named.with_remote(Some(RemoteConfig {
+            host: "buildbox".to_string(),
+            user: None,
+            port: None,
+            identity_file: None,
+            tmux_socket: None,
+            control_persist_sec: 600,
+        }));
+        assert_eq!(args(&remote).last().unwrap(), "tmux -L agents list-panes -a");
+    }
+
+    #[test]
+    fn test_parse_metadata_activity() {
+        let line = "%3|:|opencode|:|vm|:|/src|:|4242|:|120|:|40|:|0|:||:|1792298046";
+        let fields: Vec<&str> = line.split(FIELD_SEPARATOR).collect();
+        let metadata = parse_metadata(&fields).unwrap();
+        assert_eq!(metadata.pid, Some(4242));
+        assert_eq!((metadata.width, metadata.height), (120, 40));
+        assert!(!metadata.dead);
+        // 没有 pane_activity 时使用 window_activity
+        assert_eq!(metadata.activity, Some(1792298046));
+
+        let fields: Vec<&str> = "%3|:|bash|:|vm|:|/src|:|4242|:|120|:|40|:|1|:|1792298050|:|1792298046".split(FIELD_SEPARATOR).collect();
+        let metadata = parse_metadata(&fields).unwrap();
+        assert!(metadata.dead);
+        assert_eq!(metadata.activity, Some(1792298050));
+    }
+
+    #[test]
+    fn test_server_key() {
+        assert_eq!(TmuxClient::new().server_key(), "local default");
+        let named = TmuxClient::new().with_socket(Some(TmuxSocket::Name("agents".to_string())));
+        assert_eq!(named.server_key(), "local -L agents");
+    }
+
+    #[tokio::test]
+    async fn test_execute_times_out_and_reports_missing_binary() {
+        let mut sleeper = Command::new("sleep");
+        sleeper.arg("5");
+        let error = execute(sleeper, "sleep", None, Duration::from_millis(50)).await.unwrap_err();
+        assert!(matches!(error, TmuxError::TimedOut { .. }));
+
+        let missing = Command::new("/nonexistent/tmux");
+        assert!(matches!(execute(missing, "tmux", None, DEFAULT_TIMEOUT).await, Err(TmuxError::NotInstalled)));
+
+        let cat = Command::new("cat");
+        assert_eq!(execute(cat, "cat", Some("继续".as_bytes()), DEFAULT_TIMEOUT).await.unwrap(), "继续");
     }
-}
\ No newline at end of file
+}
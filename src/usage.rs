@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+use regex::{Captures, Regex, RegexBuilder};
+use crate::config::{BudgetConfig, UsageConfig};
+use crate::localtime;
+
+// 累计值连续这么多次采样都比之前小才认为 agent 重启开始了新会话，避免屏幕上新旧数值交替出现时重复累计
+const RESTART_SAMPLES: usize = 2;
+
+/// 一次采样读到的用量，屏幕上没有显示的项为 None
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub tokens: Option<u64>,
+    pub cost: Option<f64>,     // 美元
+    pub context: Option<f64>,  // 上下文已用百分比
+}
+
+impl Usage {
+    fn is_empty(&self) -> bool {
+        self.tokens.is_none() && self.cost.is_none() && self.context.is_none()
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(tokens) = self.tokens {
+            parts.push(format!("tokens {}", format_count(tokens)));
+        }
+        if let Some(cost) = self.cost {
+            parts.push(format!("费用 ${:.2}", cost));
+        }
+        if let Some(context) = self.context {
+            parts.push(format!("上下文 {:.0}%", context));
+        }
+        f.write_str(&parts.join("，"))
+    }
+}
+
+/// 从屏幕内容中解析用量
+pub struct UsageParser {
+    patterns: Vec<Regex>,
+    search_lines: usize,
+}
+
+impl UsageParser {
+    /// 无效的正则或缺少命名分组的正则会被忽略并给出警告
+    pub fn new(config: &UsageConfig) -> Self {
+        const GROUPS: [&str; 4] = ["tokens", "cost", "context", "context_left"];
+        let patterns = config
+            .patterns
+            .iter()
+            .filter_map(|pattern| match RegexBuilder::new(pattern).case_insensitive(true).build() {
+                Ok(regex) if regex.capture_names().flatten().any(|name| GROUPS.contains(&name)) => Some(regex),
+                Ok(_) => {
+                    eprintln!("⚠️ 忽略用量正则 {:?}：缺少 tokens、cost、context 或 context_left 命名分组", pattern);
+                    None
+                }
+                Err(e) => {
+                    eprintln!("⚠️ 忽略无效的用量正则 {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        UsageParser { patterns, search_lines: config.search_lines }
+    }
+
+    pub fn parse(&self, content: &str) -> Usage {
+        let lines: Vec<&str> = content.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+        let lines = match self.search_lines {
+            0 => &lines[..],
+            n => &lines[lines.len().saturating_sub(n)..],
+        };
+        Usage {
+            tokens: self.find(lines, |captures| parse_count(captures.name("tokens")?.as_str())),
+            cost: self.find(lines, |captures| parse_number(captures.name("cost")?.as_str())),
+            context: self.find(lines, |captures| match captures.name("context_left") {
+                Some(left) => parse_number(left.as_str()).map(|left| 100.0 - left),
+                None => parse_number(captures.name("context")?.as_str()),
+            }),
+        }
+    }
+
+    /// 按正则的顺序查找，每个正则从屏幕底部往上找第一个能取到值的行
+    fn find<T>(&self, lines: &[&str], value: impl Fn(&Captures) -> Option<T>) -> Option<T> {
+        self.patterns
+            .iter()
+            .find_map(|regex| lines.iter().rev().find_map(|line| value(&regex.captures(line)?)))
+    }
+}
+
+/// 单个目标的用量记录：最近的采样序列、会话累计和当天累计
+pub struct UsageTracker {
+    parser: UsageParser,
+    budget: BudgetConfig,
+    history: usize,
+    samples: VecDeque<(Instant, Usage)>,
+    current: Usage,  // 各项最近一次读到的值，即 agent 显示的会话累计
+    day: Option<i32>,
+    daily_tokens: u64,
+    daily_cost: f64,
+    lower_samples: usize,  // 连续读到比当前累计值小的采样次数
+    respawned: bool,       // 刚执行过重启命令，下一次变小的累计值直接视为新会话
+}
+
+impl UsageTracker {
+    pub fn new(config: &UsageConfig) -> Self {
+        UsageTracker {
+            parser: UsageParser::new(config),
+            budget: config.budget.clone(),
+            history: config.history.max(1),
+            samples: VecDeque::new(),
+            current: Usage::default(),
+            day: None,
+            daily_tokens: 0,
+            daily_cost: 0.0,
+            lower_samples: 0,
+            respawned: false,
+        }
+    }
+
+    /// 记录一次捕获的内容，用量有变化时返回合并后的当前用量
+    pub fn record(&mut self, content: &str) -> Option<Usage> {
+        self.record_at(Instant::now(), localtime::now().day, content)
+    }
+
+    /// 已执行重启命令
+    pub fn respawned(&mut self) {
+        self.respawned = true;
+    }
+
+    pub fn record_at(&mut self, now: Instant, day: i32, content: &str) -> Option<Usage> {
+        let usage = self.parser.parse(content);
+        if self.day != Some(day) {
+            self.day = Some(day);
+            self.daily_tokens = 0;
+            self.daily_cost = 0.0;
+        }
+        if usage.is_empty() {
+            return None;
+        }
+
+        // 累计值持续变小说明 agent 重启开始了新会话，新的累计值全部计入当天用量
+        let previous = self.current;
+        let lower = matches!((usage.tokens, previous.tokens), (Some(tokens), Some(last)) if tokens < last)
+            || matches!((usage.cost, previous.cost), (Some(cost), Some(last)) if cost < last);
+        if lower {
+            self.lower_samples += 1;
+            if self.lower_samples < RESTART_SAMPLES && !self.respawned {
+                return None;
+            }
+            self.current = Usage::default();
+            self.samples.clear();
+            self.respawned = false;
+        }
+        self.lower_samples = 0;
+
+        if let Some(tokens) = usage.tokens {
+            self.daily_tokens += tokens - self.current.tokens.unwrap_or(0);
+        }
+        if let Some(cost) = usage.cost {
+            self.daily_cost += cost - self.current.cost.unwrap_or(0.0);
+        }
+        self.current = Usage {
+            tokens: usage.tokens.or(self.current.tokens),
+            cost: usage.cost.or(self.current.cost),
+            context: usage.context.or(self.current.context),
+        };
+
+        self.samples.push_back((now, usage));
+        while self.samples.len() > self.history {
+            self.samples.pop_front();
+        }
+        (self.current != previous).then_some(self.current)
+    }
+
+    /// 最近 window 内增加的 tokens，采样不足时返回 None
+    pub fn recent_tokens(&self, window: Duration) -> Option<u64> {
+        let (now, _) = self.samples.back()?;
+        let first = self
+            .samples
+            .iter()
+            .filter(|(at, _)| now.duration_since(*at) <= window)
+            .find_map(|(_, usage)| usage.tokens)?;
+        Some(self.current.tokens?.saturating_sub(first))
+    }
+
+    /// 超出的预算，没有超出时返回 None
+    pub fn exceeded(&self) -> Option<String> {
+        let budget = &self.budget;
+        if let (Some(limit), Some(tokens)) = (budget.session_tokens, self.current.tokens) {
+            if tokens >= limit {
+                return Some(format!("本次会话 tokens {} 达到预算 {}", format_count(tokens), format_count(limit)));
+            }
+        }
+        if let (Some(limit), Some(cost)) = (budget.session_cost, self.current.cost) {
+            if cost >= limit {
+                return Some(format!("本次会话费用 ${:.2} 达到预算 ${:.2}", cost, limit));
+            }
+        }
+        if budget.daily_tokens.is_some_and(|limit| self.daily_tokens >= limit) {
+            return Some(format!("今日 tokens {} 达到预算 {}", format_count(self.daily_tokens), format_count(budget.daily_tokens?)));
+        }
+        if budget.daily_cost.is_some_and(|limit| self.daily_cost >= limit) {
+            return Some(format!("今日费用 ${:.2} 达到预算 ${:.2}", self.daily_cost, budget.daily_cost?));
+        }
+        None
+    }
+}
+
+/// 解析 "12,345"、"12.3K"、"1.2M" 这样的数量
+fn parse_count(text: &str) -> Option<u64> {
+    let text: String = text.chars().filter(|ch| !ch.is_whitespace() && *ch != ',').collect::<String>().to_lowercase();
+    let (number, scale) = match text.strip_suffix('k') {
+        Some(number) => (number, 1e3),
+        None => match text.strip_suffix('m') {
+            Some(number) => (number, 1e6),
+            None => (text.as_str(), 1.0),
+        },
+    };
+    number.parse::<f64>().ok().map(|value| (value * scale).round() as u64)
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    text.replace(',', "").trim().parse().ok()
+}
+
+/// 12345 显示为 12.3k，1234567 显示为 1.2M
+pub fn format_count(count: u64) -> String {
+    match count {
+        0..=999 => count.to_string(),
+        1_000..=999_999 => format!("{:.1}k", count as f64 / 1e3),
+        _ => format!("{:.1}M", count as f64 / 1e6),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Usage {
+        UsageParser::new(&UsageConfig::default()).parse(content)
+    }
+
+    #[test]
+    fn test_parse_agent_status_bars() {
+        assert_eq!(
+            parse("┃ build  main\n12,345 tokens (6%)  Cost: $0.42"),
+            Usage { tokens: Some(12345), cost: Some(0.42), context: Some(6.0) }
+        );
+        let aider = "Tokens: 2.1k sent, 345 received. Cost: $0.01 message, $0.12 session.";
+        assert_eq!(parse(aider), Usage { tokens: Some(2100), cost: Some(0.12), context: None });
+        assert_eq!(parse("> \n  Context left until auto-compact: 12%"), Usage { context: Some(88.0), ..Usage::default() });
+        assert_eq!(parse("tokens used: 1.2M   85% context left"), Usage { tokens: Some(1_200_000), cost: None, context: Some(15.0) });
+        assert!(parse("working on it").is_empty());
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("12.3K"), Some(12300));
+        assert_eq!(parse_count("1,024"), Some(1024));
+        assert_eq!(parse_count("2 m"), Some(2_000_000));
+        assert_eq!(format_count(12345), "12.3k");
+    }
+
+    #[test]
+    fn test_daily_total_survives_restart_and_resets_at_midnight() {
+        let config = UsageConfig { budget: BudgetConfig { daily_tokens: Some(20_000), ..BudgetConfig::default() }, ..UsageConfig::default() };
+        let mut tracker = UsageTracker::new(&config);
+        let now = Instant::now();
+        tracker.record_at(now, 1, "8,000 tokens");
+        tracker.record_at(now, 1, "12,000 tokens");
+        // agent 重启，累计值从头开始，连续两次采样都变小才算
+        tracker.record_at(now, 1, "4,000 tokens");
+        assert_eq!(tracker.daily_tokens, 12_000);
+        tracker.record_at(now, 1, "5,000 tokens");
+        assert_eq!(tracker.daily_tokens, 17_000);
+        assert_eq!(tracker.exceeded(), None);
+        tracker.record_at(now, 1, "9,000 tokens");
+        assert_eq!(tracker.exceeded().as_deref(), Some("今日 tokens 21.0k 达到预算 20.0k"));
+        // 过了零点，只统计之后的增量
+        tracker.record_at(now, 2, "9,500 tokens");
+        assert_eq!(tracker.daily_tokens, 500);
+        assert_eq!(tracker.exceeded(), None);
+    }
+
+    #[test]
+    fn test_flip_flopping_value_is_not_a_restart() {
+        let mut tracker = UsageTracker::new(&UsageConfig::default());
+        let now = Instant::now();
+        // 屏幕上新旧数值交替出现（例如滚动中的输出里引用了旧的用量）
+        for content in ["12,000 tokens", "3,000 tokens", "12,000 tokens", "3,000 tokens", "12,500 tokens"] {
+            tracker.record_at(now, 1, content);
+        }
+        assert_eq!(tracker.daily_tokens, 12_500);
+        assert_eq!(tracker.current.tokens, Some(12_500));
+        // 执行过重启命令后，第一次变小的值就是新会话
+        tracker.respawned();
+        tracker.record_at(now, 1, "12,500 tokens");
+        tracker.record_at(now, 1, "800 tokens");
+        assert_eq!(tracker.daily_tokens, 13_300);
+    }
+
+    #[test]
+    fn test_only_bottom_lines_are_searched() {
+        // 对话内容里提到的数字不是用量
+        let transcript = "> why did it stop?\nIt used 210000 tokens > 200000 and cost $3.50.\n\nsure\nfixing\ndone\nrunning tests\n┃ build main\n1,234 tokens  $0.02";
+        assert_eq!(parse(transcript), Usage { tokens: Some(1234), cost: Some(0.02), context: None });
+        assert!(parse("It used 210000 tokens and cost $3.50.\na\nb\nc\nd\ne").is_empty());
+    }
+
+    #[test]
+    fn test_session_budget_and_recent_tokens() {
+        let config = UsageConfig { budget: BudgetConfig { session_cost: Some(1.0), ..BudgetConfig::default() }, history: 3, ..UsageConfig::default() };
+        let mut tracker = UsageTracker::new(&config);
+        let start = Instant::now();
+        for (minute, content) in ["1k tokens $0.10", "3k tokens $0.40", "6k tokens $0.80", "10k tokens $1.20"].iter().enumerate() {
+            tracker.record_at(start + Duration::from_secs(60 * minute as u64), 1, content);
+        }
+        assert_eq!(tracker.samples.len(), 3);
+        assert_eq!(tracker.recent_tokens(Duration::from_secs(120)), Some(7000));
+        assert_eq!(tracker.exceeded().as_deref(), Some("本次会话费用 $1.20 达到预算 $1.00"));
+    }
+}
@@ -0,0 +1,701 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use crate::ansi::{apply_sgr, Color, Style, StyledCell, StyledLine, StyledScreen};
+
+/// 内置的虚拟终端屏幕
+/// 解释常见的控制字符、CSI 序列和 SGR 样式，维护带样式的单元格网格，
+/// 用于在不依赖 capture-pane 的情况下从原始输出字节流还原屏幕内容
+pub struct VtScreen {
+    rows: usize,
+    cols: usize,
+    grid: Vec<Vec<StyledCell>>,
+    cursor_row: usize,
+    cursor_col: usize,
+    wrap_pending: bool,     // 光标停在最后一列之后，下一个字符需要先换行
+    pen: Style,             // 当前 SGR 样式，写入的字符使用该样式
+    scroll_top: usize,      // 滚动区域首行（CSI r）
+    scroll_bottom: usize,   // 滚动区域末行（含）
+    saved_cursor: Option<SavedCursor>,      // ESC 7 / CSI s 保存的光标
+    primary: Option<Vec<Vec<StyledCell>>>,  // 处于备用屏幕时保存的主屏幕内容
+    cursor_visible: bool,   // 光标是否可见（CSI ?25 h/l）
+    bracketed_paste: bool,  // 程序是否开启了 bracketed paste（CSI ?2004 h）
+    scrollback: VecDeque<Vec<StyledCell>>,  // 从主屏幕顶部滚出的行
+    scrollback_limit: usize,
+    state: ParseState,
+    utf8_buf: Vec<u8>,      // 未完整的 UTF-8 字节
+}
+
+/// 宽字符右半部分的占位符
+const WIDE_TAIL: char = '\0';
+
+/// 空白单元格
+const BLANK: StyledCell = StyledCell {
+    ch: ' ',
+    style: Style {
+        fg: Color::Default,
+        bg: Color::Default,
+        bold: false,
+        dim: false,
+        italic: false,
+        underline: false,
+        reverse: false,
+    },
+};
+
+#[derive(Debug, Clone, Copy)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    pen: Style,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ParseState {
+    Ground,
+    Escape,
+    Csi(String),
+    Osc { escape: bool },
+}
+
+impl VtScreen {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        VtScreen {
+            rows,
+            cols,
+            grid: vec![vec![BLANK; cols]; rows],
+            cursor_row: 0,
+            cursor_col: 0,
+            wrap_pending: false,
+            pen: Style::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            saved_cursor: None,
+            primary: None,
+            cursor_visible: true,
+            bracketed_paste: false,
+            scrollback: VecDeque::new(),
+            scrollback_limit: 0,
+            state: ParseState::Ground,
+            utf8_buf: Vec::new(),
+        }
+    }
+
+    /// 保留最多 limit 行滚出主屏幕的历史
+    pub fn with_scrollback(mut self, limit: usize) -> Self {
+        self.scrollback_limit = limit;
+        self
+    }
+
+    /// 屏幕尺寸 (行, 列)
+    pub fn size(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    /// 光标位置 (行, 列)，从 0 开始
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_row, self.cursor_col)
+    }
+
+    /// 光标是否可见
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// 是否处于备用屏幕（全屏程序通常用 CSI ?1049 h 切换）
+    pub fn alternate_screen(&self) -> bool {
+        self.primary.is_some()
+    }
+
+    /// 程序是否开启了 bracketed paste
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste
+    }
+
+    /// 调整屏幕尺寸，保留左上角已有内容
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        // 行数减少时丢弃顶部的行，保持光标所在内容可见；主屏幕丢弃的行进入滚动历史
+        let overflow = self.rows.saturating_sub(rows).min(self.cursor_row);
+        let dropped = resize_grid(&mut self.grid, rows, cols, overflow);
+        let dropped = match &mut self.primary {
+            Some(primary) => resize_grid(primary, rows, cols, overflow),
+            None => dropped,
+        };
+        for line in dropped {
+            self.push_scrollback(line);
+        }
+        self.rows = rows;
+        self.cols = cols;
+        self.cursor_row = (self.cursor_row - overflow).min(rows - 1);
+        self.cursor_col = self.cursor_col.min(cols - 1);
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.wrap_pending = false;
+    }
+
+    /// 屏幕纯文本，每行去掉行尾空格
+    pub fn text(&self) -> String {
+        self.grid
+            .iter()
+            .map(|line| {
+                let text: String = line.iter().map(|cell| cell.ch).filter(|&ch| ch != WIDE_TAIL).collect();
+                format!("{}\n", text.trim_end())
+            })
+            .collect()
+    }
+
+    /// 带样式的屏幕内容，处于主屏幕时包含滚动历史
+    /// 每行去掉行尾的空白单元格，与 capture-pane 的输出形式一致
+    pub fn styled_screen(&self) -> StyledScreen {
+        let history = if self.primary.is_none() { self.scrollback.len() } else { 0 };
+        let lines = self
+            .scrollback
+            .iter()
+            .take(history)
+            .chain(self.grid.iter())
+            .map(|line| {
+                let mut cells: Vec<StyledCell> = line.iter().copied().filter(|cell| cell.ch != WIDE_TAIL).collect();
+                while cells.last() == Some(&BLANK) {
+                    cells.pop();
+                }
+                StyledLine { cells }
+            })
+            .collect();
+        StyledScreen { lines, visible_start: history }
+    }
+
+    /// 输入一段终端输出字节
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if !self.utf8_buf.is_empty() || byte >= 0x80 {
+                self.utf8_buf.push(byte);
+                match std::str::from_utf8(&self.utf8_buf) {
+                    Ok(text) => {
+                        let ch = text.chars().next().unwrap_or('\u{fffd}');
+                        self.utf8_buf.clear();
+                        self.process_char(ch);
+                    }
+                    Err(e) if e.error_len().is_some() || self.utf8_buf.len() >= 4 => {
+                        self.utf8_buf.clear();
+                        self.process_char('\u{fffd}');
+                    }
+                    Err(_) => {}
+                }
+                continue;
+            }
+            self.process_char(byte as char);
+        }
+    }
+
+    fn process_char(&mut self, ch: char) {
+        match std::mem::replace(&mut self.state, ParseState::Ground) {
+            ParseState::Ground => self.ground(ch),
+            ParseState::Escape => self.escape(ch),
+            ParseState::Csi(mut params) => {
+                if ('\x40'..='\x7e').contains(&ch) {
+                    self.csi(&params, ch);
+                } else if params.len() < 64 {
+                    params.push(ch);
+                    self.state = ParseState::Csi(params);
+                }
+            }
+            ParseState::Osc { escape } => {
+                // OSC 以 BEL 或 ESC \ 结束
+                if ch == '\x07' || (escape && ch == '\\') {
+                    return;
+                }
+                self.state = ParseState::Osc { escape: ch == '\x1b' };
+            }
+        }
+    }
+
+    fn ground(&mut self, ch: char) {
+        match ch {
+            '\x1b' => self.state = ParseState::Escape,
+            '\r' => {
+                self.cursor_col = 0;
+                self.wrap_pending = false;
+            }
+            '\n' | '\x0b' | '\x0c' => self.line_feed(),
+            '\x08' => {
+                self.cursor_col = self.cursor_col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            '\t' => {
+                self.cursor_col = ((self.cursor_col / 8) + 1) * 8;
+                self.cursor_col = self.cursor_col.min(self.cols - 1);
+            }
+            c if (c as u32) < 0x20 || c == '\x7f' => {}
+            c => self.put_char(c),
+        }
+    }
+
+    fn escape(&mut self, ch: char) {
+        match ch {
+            '[' => self.state = ParseState::Csi(String::new()),
+            ']' => self.state = ParseState::Osc { escape: false },
+            'D' => self.line_feed(),
+            'E' => {
+                self.cursor_col = 0;
+                self.line_feed();
+            }
+            'M' => self.reverse_index(),
+            '7' => self.save_cursor(),
+            '8' => self.restore_cursor(),
+            'c' => *self = VtScreen::new(self.rows, self.cols).with_scrollback(self.scrollback_limit),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, params: &str, action: char) {
+        let private = params.starts_with(['?', '>', '=']);
+        let numbers: Vec<usize> = params
+            .trim_start_matches(['?', '>', '='])
+            .split(';')
+            // 参数限制在 u16 范围内，巨大的数值不会让光标计算溢出
+            .map(|p| p.parse::<usize>().map_or(0, |n| n.min(u16::MAX as usize)))
+            .collect();
+        let arg = |i: usize, default: usize| numbers.get(i).copied().filter(|&n| n != 0).unwrap_or(default);
+
+        if private {
+            if params.starts_with('?') && matches!(action, 'h' | 'l') {
+                for &mode in &numbers {
+                    self.set_private_mode(mode, action == 'h');
+                }
+            }
+            return;
+        }
+
+        self.wrap_pending = false;
+        match action {
+            'A' => self.cursor_row = self.cursor_row.saturating_sub(arg(0, 1)),
+            'B' | 'e' => self.cursor_row = (self.cursor_row + arg(0, 1)).min(self.rows - 1),
+            'C' | 'a' => self.cursor_col = (self.cursor_col + arg(0, 1)).min(self.cols - 1),
+            'D' => self.cursor_col = self.cursor_col.saturating_sub(arg(0, 1)),
+            'E' => {
+                self.cursor_row = (self.cursor_row + arg(0, 1)).min(self.rows - 1);
+                self.cursor_col = 0;
+            }
+            'F' => {
+                self.cursor_row = self.cursor_row.saturating_sub(arg(0, 1));
+                self.cursor_col = 0;
+            }
+            'G' | '`' => self.cursor_col = (arg(0, 1) - 1).min(self.cols - 1),
+            'd' => self.cursor_row = (arg(0, 1) - 1).min(self.rows - 1),
+            'H' | 'f' => {
+                self.cursor_row = (arg(0, 1) - 1).min(self.rows - 1);
+                self.cursor_col = (arg(1, 1) - 1).min(self.cols - 1);
+            }
+            'J' => self.erase_display(numbers.first().copied().unwrap_or(0)),
+            'K' => self.erase_line(numbers.first().copied().unwrap_or(0)),
+            'L' if self.in_scroll_region() => {
+                for _ in 0..arg(0, 1).min(self.scroll_bottom - self.cursor_row + 1) {
+                    self.grid.remove(self.scroll_bottom);
+                    self.grid.insert(self.cursor_row, vec![BLANK; self.cols]);
+                }
+            }
+            'M' if self.in_scroll_region() => {
+                for _ in 0..arg(0, 1).min(self.scroll_bottom - self.cursor_row + 1) {
+                    self.grid.remove(self.cursor_row);
+                    self.grid.insert(self.scroll_bottom, vec![BLANK; self.cols]);
+                }
+            }
+            'P' => {
+                let line = &mut self.grid[self.cursor_row];
+                for _ in 0..arg(0, 1).min(self.cols - self.cursor_col) {
+                    line.remove(self.cursor_col);
+                    line.push(BLANK);
+                }
+            }
+            '@' => {
+                let line = &mut self.grid[self.cursor_row];
+                for _ in 0..arg(0, 1).min(self.cols - self.cursor_col) {
+                    line.insert(self.cursor_col, BLANK);
+                    line.pop();
+                }
+            }
+            'X' => {
+                let end = self.cursor_col.saturating_add(arg(0, 1)).min(self.cols);
+                self.grid[self.cursor_row][self.cursor_col..end].fill(BLANK);
+            }
+            'S' => {
+                for _ in 0..arg(0, 1).min(self.rows) {
+                    self.scroll_up();
+                }
+            }
+            'T' => {
+                for _ in 0..arg(0, 1).min(self.rows) {
+                    self.scroll_down();
+                }
+            }
+            'm' => apply_sgr(&mut self.pen, params),
+            'r' => {
+                let top = arg(0, 1) - 1;
+                let bottom = arg(1, self.rows) - 1;
+                if top < bottom && bottom < self.rows {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.cursor_row = 0;
+                    self.cursor_col = 0;
+                }
+            }
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
+            _ => {}
+        }
+    }
+
+    /// DEC 私有模式（CSI ? N h/l）
+    fn set_private_mode(&mut self, mode: usize, enabled: bool) {
+        match mode {
+            25 => self.cursor_visible = enabled,
+            2004 => self.bracketed_paste = enabled,
+            1049 if enabled => {
+                self.save_cursor();
+                self.enter_alternate();
+            }
+            1049 => {
+                self.leave_alternate();
+                self.restore_cursor();
+            }
+            47 | 1047 if enabled => self.enter_alternate(),
+            47 | 1047 => self.leave_alternate(),
+            _ => {}
+        }
+    }
+
+    /// 切换到清空的备用屏幕，保存主屏幕内容
+    fn enter_alternate(&mut self) {
+        if self.primary.is_none() {
+            let blank = vec![vec![BLANK; self.cols]; self.rows];
+            self.primary = Some(std::mem::replace(&mut self.grid, blank));
+        }
+    }
+
+    /// 回到主屏幕，丢弃备用屏幕的内容
+    fn leave_alternate(&mut self) {
+        if let Some(primary) = self.primary.take() {
+            self.grid = primary;
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor { row: self.cursor_row, col: self.cursor_col, pen: self.pen });
+    }
+
+    fn restore_cursor(&mut self) {
+        if let Some(saved) = self.saved_cursor {
+            self.cursor_row = saved.row.min(self.rows - 1);
+            self.cursor_col = saved.col.min(self.cols - 1);
+            self.pen = saved.pen;
+        }
+        self.wrap_pending = false;
+    }
+
+    fn in_scroll_region(&self) -> bool {
+        (self.scroll_top..=self.scroll_bottom).contains(&self.cursor_row)
+    }
+
+    fn put_char(&mut self, ch: char) {
+        let width = char_width(ch);
+        if self.wrap_pending || self.cursor_col + width > self.cols {
+            self.cursor_col = 0;
+            self.line_feed();
+        }
+
+        self.grid[self.cursor_row][self.cursor_col] = StyledCell { ch, style: self.pen };
+        if width == 2 && self.cursor_col + 1 < self.cols {
+            self.grid[self.cursor_row][self.cursor_col + 1] = StyledCell { ch: WIDE_TAIL, style: self.pen };
+        }
+
+        if self.cursor_col + width >= self.cols {
+            self.cursor_col = self.cols - 1;
+            self.wrap_pending = true;
+        } else {
+            self.cursor_col += width;
+        }
+    }
+
+    fn line_feed(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor_row == self.scroll_bottom {
+            self.scroll_up();
+        } else if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cursor_row == self.scroll_top {
+            self.scroll_down();
+        } else {
+            self.cursor_row = self.cursor_row.saturating_sub(1);
+        }
+    }
+
+    /// 滚动区域上移一行；主屏幕整屏滚动时，滚出的行进入滚动历史
+    fn scroll_up(&mut self) {
+        let line = self.grid.remove(self.scroll_top);
+        self.grid.insert(self.scroll_bottom, vec![BLANK; self.cols]);
+        if self.scroll_top == 0 && self.primary.is_none() {
+            self.push_scrollback(line);
+        }
+    }
+
+    fn scroll_down(&mut self) {
+        self.grid.remove(self.scroll_bottom);
+        self.grid.insert(self.scroll_top, vec![BLANK; self.cols]);
+    }
+
+    fn push_scrollback(&mut self, line: Vec<StyledCell>) {
+        if self.scrollback_limit == 0 {
+            return;
+        }
+        self.scrollback.push_back(line);
+        while self.scrollback.len() > self.scrollback_limit {
+            self.scrollback.pop_front();
+        }
+    }
+
+    fn erase_display(&mut self, mode: usize) {
+        match mode {
+            0 => {
+                self.erase_line(0);
+                for line in &mut self.grid[self.cursor_row + 1..] {
+                    line.fill(BLANK);
+                }
+            }
+            1 => {
+                self.erase_line(1);
+                for line in &mut self.grid[..self.cursor_row] {
+                    line.fill(BLANK);
+                }
+            }
+            _ => {
+                for line in &mut self.grid {
+                    line.fill(BLANK);
+                }
+            }
+        }
+    }
+
+    fn erase_line(&mut self, mode: usize) {
+        let line = &mut self.grid[self.cursor_row];
+        match mode {
+            0 => line[self.cursor_col..].fill(BLANK),
+            1 => line[..=self.cursor_col].fill(BLANK),
+            _ => line.fill(BLANK),
+        }
+    }
+}
+
+/// 调整网格尺寸，先丢弃顶部 overflow 行，返回被丢弃的行
+/// 读取线程和监控循环共享的虚拟终端加锁
+/// 解析输出时 panic 会让锁中毒，这里照常取得屏幕，避免之后的每次捕获都失败
+pub fn lock_screen(screen: &Mutex<VtScreen>) -> MutexGuard<'_, VtScreen> {
+    screen.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// 在读取线程中把输出送入共享的虚拟终端，解析出错时丢弃这段输出并返回 false
+pub fn feed_screen(screen: &Mutex<VtScreen>, bytes: &[u8]) -> bool {
+    panic::catch_unwind(AssertUnwindSafe(|| lock_screen(screen).feed(bytes))).is_ok()
+}
+
+fn resize_grid(grid: &mut Vec<Vec<StyledCell>>, rows: usize, cols: usize, overflow: usize) -> Vec<Vec<StyledCell>> {
+    for line in grid.iter_mut() {
+        line.resize(cols, BLANK);
+    }
+    let dropped = grid.drain(..overflow.min(grid.len())).collect();
+    grid.resize(rows, vec![BLANK; cols]);
+    dropped
+}
+
+/// 字符显示宽度：东亚宽字符和常见 emoji 占两列
+pub fn char_width(ch: char) -> usize {
+    let code = ch as u32;
+    let wide = matches!(code,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD);
+    if wide { 2 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(screen: &VtScreen) -> Vec<String> {
+        screen.text().lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_huge_csi_params_do_not_panic() {
+        let mut vt = VtScreen::new(3, 10);
+        vt.feed(b"hello\x1b[18446744073709551615X");
+        assert_eq!(lines(&vt), vec!["hello", "", ""]);
+        let huge = ["18446744073709551615", "99999999999999999999999", "4294967296", "65536"];
+        for action in "ABCDEFGHJKLMPSTXde`afr@".chars() {
+            for param in huge {
+                vt.feed(format!("ab\x1b[{}{}\x1b[{};{}{}", param, action, param, param, action).as_bytes());
+            }
+        }
+        vt.feed(b"\x1b[2;5Hok");
+        assert_eq!(&lines(&vt)[1][4..], "ok");
+    }
+
+    #[test]
+    fn test_poisoned_screen_is_recovered() {
+        let screen = std::sync::Arc::new(Mutex::new(VtScreen::new(2, 10)));
+        let poisoner = screen.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("解析出错");
+        })
+        .join();
+        assert!(screen.is_poisoned());
+        assert!(feed_screen(&screen, b"still ok"));
+        assert_eq!(lock_screen(&screen).text().lines().next(), Some("still ok"));
+    }
+
+    #[test]
+    fn test_plain_output_and_newlines() {
+        let mut vt = VtScreen::new(3, 10);
+        vt.feed(b"hello\r\nworld");
+        assert_eq!(lines(&vt), vec!["hello", "world", ""]);
+    }
+
+    #[test]
+    fn test_scrolls_when_full() {
+        let mut vt = VtScreen::new(2, 10);
+        vt.feed(b"a\r\nb\r\nc");
+        assert_eq!(lines(&vt), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_wraps_long_lines() {
+        let mut vt = VtScreen::new(3, 4);
+        vt.feed(b"abcdef");
+        assert_eq!(lines(&vt), vec!["abcd", "ef", ""]);
+    }
+
+    #[test]
+    fn test_cursor_movement_and_erase() {
+        let mut vt = VtScreen::new(3, 10);
+        vt.feed(b"working...\x1b[2;1Hline two\x1b[1;1H\x1b[2Kdone");
+        assert_eq!(lines(&vt), vec!["done", "line two", ""]);
+
+        vt.feed(b"\x1b[2J\x1b[H> ");
+        assert_eq!(lines(&vt), vec![">", "", ""]);
+    }
+
+    #[test]
+    fn test_carriage_return_overwrite() {
+        let mut vt = VtScreen::new(1, 20);
+        vt.feed(b"progress 10%\rprogress 99%");
+        assert_eq!(lines(&vt), vec!["progress 99%"]);
+    }
+
+    #[test]
+    fn test_utf8_split_across_feeds_and_wide_chars() {
+        let mut vt = VtScreen::new(1, 10);
+        let bytes = "继续ok".as_bytes();
+        vt.feed(&bytes[..2]);
+        vt.feed(&bytes[2..]);
+        assert_eq!(lines(&vt), vec!["继续ok"]);
+        assert_eq!(vt.cursor(), (0, 6));
+    }
+
+    #[test]
+    fn test_ignores_osc_and_tracks_cursor_visibility() {
+        let mut vt = VtScreen::new(1, 20);
+        vt.feed(b"\x1b]0;title\x07\x1b[1;32mgreen\x1b[0m \x1b[?25lok");
+        assert_eq!(lines(&vt), vec!["green ok"]);
+        assert!(!vt.cursor_visible());
+    }
+
+    #[test]
+    fn test_sgr_styles_cells() {
+        let mut vt = VtScreen::new(1, 20);
+        vt.feed(b"\x1b[1;31mERR\x1b[0m ok");
+        let screen = vt.styled_screen();
+        let cells = &screen.lines[0].cells;
+        assert_eq!(cells[0].style.fg, Color::Indexed(1));
+        assert!(cells[0].style.bold);
+        assert_eq!(cells[4].style, Style::default());
+        assert_eq!(screen.lines[0].text(), "ERR ok");
+    }
+
+    #[test]
+    fn test_alternate_screen_restores_primary() {
+        let mut vt = VtScreen::new(2, 12);
+        vt.feed(b"$ opencode");
+        vt.feed(b"\x1b[?1049h\x1b[Hworking");
+        assert!(vt.alternate_screen());
+        assert_eq!(lines(&vt), vec!["working", ""]);
+
+        vt.feed(b"\x1b[?1049l");
+        assert!(!vt.alternate_screen());
+        assert_eq!(lines(&vt), vec!["$ opencode", ""]);
+        assert_eq!(vt.cursor(), (0, 10));
+    }
+
+    #[test]
+    fn test_save_and_restore_cursor() {
+        let mut vt = VtScreen::new(3, 10);
+        vt.feed(b"\x1b[2;3H\x1b7\x1b[Hstatus\x1b8x");
+        assert_eq!(lines(&vt), vec!["status", "  x", ""]);
+        vt.feed(b"\x1b[3;1H\x1b[s\x1b[1;1H\x1b[uy");
+        assert_eq!(vt.cursor(), (2, 1));
+    }
+
+    #[test]
+    fn test_scroll_region_keeps_status_line() {
+        let mut vt = VtScreen::new(3, 10);
+        vt.feed(b"\x1b[3;1Hstatus\x1b[1;2r\x1b[1;1Ha\r\nb\r\nc");
+        assert_eq!(lines(&vt), vec!["b", "c", "status"]);
+    }
+
+    #[test]
+    fn test_scrollback_in_styled_screen() {
+        let mut vt = VtScreen::new(2, 10).with_scrollback(1);
+        vt.feed(b"1\r\n2\r\n3\r\n4");
+        let screen = vt.styled_screen();
+        assert_eq!(screen.visible_start, 1);
+        assert_eq!(screen.full_text(), "2\n3\n4\n");
+        assert_eq!(screen.visible_text(), "3\n4\n");
+    }
+
+    #[test]
+    fn test_tracks_bracketed_paste_mode() {
+        let mut vt = VtScreen::new(1, 10);
+        assert!(!vt.bracketed_paste());
+        vt.feed(b"\x1b[?2004h");
+        assert!(vt.bracketed_paste());
+        vt.feed(b"\x1b[?2004l");
+        assert!(!vt.bracketed_paste());
+    }
+
+    #[test]
+    fn test_resize_keeps_cursor_line() {
+        let mut vt = VtScreen::new(4, 10);
+        vt.feed(b"1\r\n2\r\n3\r\n4");
+        vt.resize(2, 5);
+        assert_eq!(lines(&vt), vec!["3", "4"]);
+        assert_eq!(vt.size(), (2, 5));
+    }
+}